@@ -0,0 +1,90 @@
+//! Minimal HTTP load generator used for the numbers in `bench/README.md`, for machines without `wrk`.
+//!
+//! Every request opens a fresh connection, sends `GET /` and reads until the response ends in `</h1>` or the
+//! server closes the connection.
+//!
+//! ```bash
+//! cargo run --release --example loadgen -- 127.0.0.1:2024 50 5
+//! ```
+
+use std::time::{Duration, Instant};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+async fn request(address: &str, buf: &mut [u8]) -> std::io::Result<()> {
+    let mut stream = TcpStream::connect(address).await?;
+    stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await?;
+
+    let mut read = 0;
+    loop {
+        let n = stream.read(&mut buf[read..]).await?;
+        if n == 0 {
+            break;
+        }
+        read += n;
+        if buf[..read].ends_with(b"</h1>") || read == buf.len() {
+            break;
+        }
+    }
+    if read == 0 {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 4 {
+        eprintln!("usage: loadgen <address> <connections> <seconds>");
+        std::process::exit(2);
+    }
+    let address = args[1].clone();
+    let connections: usize = args[2].parse().expect("connections must be a number");
+    let seconds: u64 = args[3].parse().expect("seconds must be a number");
+    let deadline = Instant::now() + Duration::from_secs(seconds);
+
+    let tasks: Vec<_> = (0..connections)
+        .map(|_| {
+            let address = address.clone();
+            tokio::spawn(async move {
+                let (mut errors, mut latencies) = (0, Vec::new());
+                let mut buf = vec![0; 4096];
+                while Instant::now() < deadline {
+                    let start = Instant::now();
+                    match request(&address, &mut buf).await {
+                        Ok(()) => latencies.push(start.elapsed()),
+                        Err(_) => errors += 1,
+                    }
+                }
+                (errors, latencies)
+            })
+        })
+        .collect();
+
+    let (mut errors, mut latencies) = (0, Vec::new());
+    for task in tasks {
+        let (task_errors, task_latencies) = task.await.expect("load task panicked");
+        errors += task_errors;
+        latencies.extend(task_latencies);
+    }
+    if latencies.is_empty() {
+        eprintln!("no successful requests, {} errors", errors);
+        std::process::exit(1);
+    }
+
+    latencies.sort();
+    let average = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+    println!(
+        "{} requests, {} errors, {:.2} req/s, latency avg {:.2?} p99 {:.2?} max {:.2?}",
+        latencies.len(),
+        errors,
+        latencies.len() as f64 / seconds as f64,
+        average,
+        latencies[latencies.len() * 99 / 100],
+        latencies[latencies.len() - 1]
+    );
+}
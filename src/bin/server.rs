@@ -9,33 +9,35 @@ async fn main() -> Result<()> {
 
     async fn hello_world_handler(ctx: &mut Context) {
         let resp = b"<h1>Hello World</h1>";
-        let mut response = ctx.response.lock().await;
+        let response = ctx.response_mut();
         response.content_type("text/html");
         response.write_body(resp);
     }
 
     async fn hello_name_handler(ctx: &mut Context) {
-        let mut response = ctx.response.lock().await;
+        let name = ctx
+            .path_params
+            .get("name")
+            .cloned()
+            .unwrap_or_else(|| "World".to_string());
+
+        let response = ctx.response_mut();
         response.content_type("text/html");
         response.write_body(b"<h1>Hello ");
-
-        let params = &ctx.path_params;
-        let name = params.get(":name").map_or(b"World" as &[u8], |name| name.as_bytes());
-
-        response.write_body(name);
+        response.write_body(name.as_bytes());
         response.write_body(b"</h1>");
     }
 
     async fn not_found_handler(ctx: &mut Context) {
         let resp = b"404";
-        let mut response = ctx.response.lock().await;
+        let response = ctx.response_mut();
         response.status_code(StatusCode::NotFound);
         response.write_body(resp);
     }
 
     router.get("/", hello_world_handler);
     router.get("/:name", hello_name_handler);
-    router.any("*", not_found_handler);
+    router.fallback(not_found_handler);
 
     server.apply(router);
 
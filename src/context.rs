@@ -1,30 +1,60 @@
 use std::collections::HashMap;
-use std::sync::Arc;
-
-use tokio::sync::Mutex;
 
+use crate::Extensions;
+use crate::Host;
 use crate::Request;
 use crate::Response;
 
 pub struct Context {
-    pub request: Arc<Mutex<Request>>,
-    pub response: Arc<Mutex<Response>>,
+    request: Request,
+    response: Response,
     pub path: String,
+    /// Normalized host of the request, see [`Request::host`].
+    pub host: Option<Host>,
     pub path_params: HashMap<String, String>,
+    /// `key=value` pairs of the query string, as sent and without percent-decoding. The first value of a
+    /// repeated key is kept, and a key without `=` maps to an empty string.
     pub query_params: HashMap<String, String>,
+    pub extensions: Extensions,
 }
 
 impl Context {
     pub fn new(request: Request, response: Response) -> Self {
         Context {
-            request: Arc::new(Mutex::new(request)),
-            response: Arc::new(Mutex::new(response)),
+            host: request.host(),
+            query_params: request.target.query().map(query_params).unwrap_or_default(),
+            request,
+            response,
             path: "".to_string(),
             path_params: HashMap::new(),
-            query_params: HashMap::new(),
+            extensions: Extensions::new(),
         }
     }
 
+    pub fn request(&self) -> &Request {
+        &self.request
+    }
+
+    pub fn request_mut(&mut self) -> &mut Request {
+        &mut self.request
+    }
+
+    pub fn response(&self) -> &Response {
+        &self.response
+    }
+
+    pub fn response_mut(&mut self) -> &mut Response {
+        &mut self.response
+    }
+
+    pub fn into_response(self) -> Response {
+        self.response
+    }
+
+    pub fn into_parts(self) -> (Request, Response) {
+        (self.request, self.response)
+    }
+
     pub fn set_path(&mut self, path: String) {
         self.path = path;
     }
@@ -36,4 +66,53 @@ impl Context {
     pub fn set_query_params(&mut self, query_params: HashMap<String, String>) {
         self.query_params = query_params;
     }
+
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.extensions.insert(value)
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.extensions.get()
+    }
+
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.extensions.get_mut()
+    }
+
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.extensions.remove()
+    }
+}
+
+fn query_params(query: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        params.entry(key.to_string()).or_insert_with(|| value.to_string());
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn query_params_from_target() {
+        let request = Request::new(Bytes::from_static(
+            b"GET /search?q=rust&page=2&q=go&flag&&empty= HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        ))
+        .expect("parsing request");
+        let ctx = Context::new(request, Response::new());
+
+        assert_eq!(ctx.query_params.len(), 4);
+        assert_eq!(ctx.query_params["q"], "rust");
+        assert_eq!(ctx.query_params["page"], "2");
+        assert_eq!(ctx.query_params["flag"], "");
+        assert_eq!(ctx.query_params["empty"], "");
+
+        let request = Request::new(Bytes::from_static(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")).unwrap();
+        assert!(Context::new(request, Response::new()).query_params.is_empty());
+    }
 }
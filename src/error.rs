@@ -1,6 +1,7 @@
+use httpstatus::StatusCode;
 use thiserror::Error;
 
-#[derive(Error, Debug)]
+#[derive(Error, Debug, PartialEq, Eq)]
 pub enum RequestError {
     #[error("invalid method")]
     Method,
@@ -10,6 +11,8 @@ pub enum RequestError {
     HeaderValue,
     #[error("invalid header body length")]
     HeaderContentLength,
+    #[error("transfer-encoding not supported")]
+    TransferEncoding,
     #[error("incomplete body")]
     IncompleteBody,
     #[error("invalid status")]
@@ -24,8 +27,53 @@ pub enum RequestError {
     Token,
     #[error("invalid uri")]
     URI,
+    #[error("missing, duplicate or disallowed host")]
+    Host,
     #[error("too many headers")]
     TooManyHeaders,
+    #[error("uri too long")]
+    URITooLong,
+    #[error("header section too large")]
+    HeadersTooLarge,
+    #[error("body too large")]
+    BodyTooLarge,
+    #[error("request timed out")]
+    Timeout,
+    #[error("request does not end where it was framed")]
+    Framing,
+}
+
+impl RequestError {
+    pub fn at(self, offset: usize) -> ParseError {
+        ParseError { kind: self, offset }
+    }
+
+    /// Status the server answers with when a request fails to parse.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            RequestError::Method | RequestError::TransferEncoding => StatusCode::NotImplemented,
+            RequestError::Version => StatusCode::HttpVersionNotSupported,
+            RequestError::URITooLong => StatusCode::UriTooLong,
+            RequestError::TooManyHeaders | RequestError::HeadersTooLarge => StatusCode::RequestHeaderFieldsTooLarge,
+            RequestError::BodyTooLarge => StatusCode::PayloadTooLarge,
+            RequestError::Timeout => StatusCode::RequestTimeout,
+            _ => StatusCode::BadRequest,
+        }
+    }
+}
+
+/// A [`RequestError`] with the byte offset into the request of the element that failed to parse.
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("{kind} at byte {offset}")]
+pub struct ParseError {
+    pub kind: RequestError,
+    pub offset: usize,
+}
+
+impl ParseError {
+    pub fn status(&self) -> StatusCode {
+        self.kind.status()
+    }
 }
 
 #[derive(Error, Debug)]
@@ -34,4 +82,10 @@ pub enum HeaderError {
     NotFound,
     #[error("header value is not a valid string")]
     InvalidString,
+    #[error("invalid header name")]
+    InvalidName,
+    #[error("header value contains a control character such as CR or LF")]
+    InvalidValue,
+    #[error("malformed header value")]
+    Malformed,
 }
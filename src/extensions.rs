@@ -0,0 +1,106 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt,
+};
+
+/// Type-keyed map used to pass values between middleware and handlers.
+/// Each type can be stored at most once; inserting the same type again replaces the previous value.
+#[derive(Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+    pub fn new() -> Self {
+        Extensions { map: HashMap::new() }
+    }
+
+    pub fn insert<T: Send + Sync + 'static>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|prev| prev.downcast().ok().map(|boxed| *boxed))
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<&T> {
+        self.map.get(&TypeId::of::<T>()).and_then(|value| value.downcast_ref())
+    }
+
+    pub fn get_mut<T: Send + Sync + 'static>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    pub fn remove<T: Send + Sync + 'static>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok().map(|boxed| *boxed))
+    }
+
+    pub fn contains<T: Send + Sync + 'static>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear()
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Extensions").field("len", &self.map.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct User(String);
+
+    #[derive(Debug, PartialEq)]
+    struct RequestId(u64);
+
+    #[test]
+    fn insert_and_get() {
+        let mut extensions = Extensions::new();
+        assert_eq!(extensions.insert(User("alice".to_string())), None);
+        assert_eq!(extensions.insert(RequestId(1)), None);
+
+        assert_eq!(extensions.get::<User>(), Some(&User("alice".to_string())));
+        assert_eq!(extensions.get::<RequestId>(), Some(&RequestId(1)));
+        assert_eq!(extensions.get::<String>(), None);
+        assert_eq!(extensions.len(), 2);
+    }
+
+    #[test]
+    fn insert_replaces_same_type() {
+        let mut extensions = Extensions::new();
+        extensions.insert(RequestId(1));
+        assert_eq!(extensions.insert(RequestId(2)), Some(RequestId(1)));
+        assert_eq!(extensions.get::<RequestId>(), Some(&RequestId(2)));
+    }
+
+    #[test]
+    fn get_mut_and_remove() {
+        let mut extensions = Extensions::new();
+        extensions.insert(User("alice".to_string()));
+
+        if let Some(user) = extensions.get_mut::<User>() {
+            user.0.push_str("@example.com");
+        }
+        assert_eq!(extensions.remove::<User>(), Some(User("alice@example.com".to_string())));
+        assert!(!extensions.contains::<User>());
+        assert!(extensions.is_empty());
+    }
+}
@@ -0,0 +1,1012 @@
+use std::fmt::Write;
+
+use crate::{Credentials, HeaderError, Method};
+
+/// A header with a typed value, read with `typed_get` and written with `typed_set` on
+/// [`crate::RequestHeaders`] and [`crate::Response`].
+pub trait Header: Sized {
+    /// Field name as written on the wire. Lookups are case-insensitive.
+    const NAME: &'static str;
+
+    /// Parses the field value. Repeated fields arrive joined with `", "`.
+    fn decode(value: &str) -> Result<Self, HeaderError>;
+
+    fn encode(&self) -> String;
+}
+
+/// Splits a comma-separated list, skipping empty elements as RFC 9110 requires.
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+fn decode_list(value: &str) -> Result<Vec<String>, HeaderError> {
+    let items: Vec<String> = split_list(value).map(str::to_string).collect();
+    if items.is_empty() {
+        return Err(HeaderError::Malformed);
+    }
+    Ok(items)
+}
+
+fn decode_number(value: &str) -> Result<u64, HeaderError> {
+    let value = value.trim();
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(HeaderError::Malformed);
+    }
+    value.parse().map_err(|_| HeaderError::Malformed)
+}
+
+fn decode_text(value: &str) -> Result<String, HeaderError> {
+    match value.trim() {
+        "" => Err(HeaderError::Malformed),
+        value => Ok(value.to_string()),
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// A media type such as `text/html; charset=utf-8`. The type and parameter names are lowercased.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentType {
+    pub mime: String,
+    pub params: Vec<(String, String)>,
+}
+
+impl ContentType {
+    pub fn new(mime: &str) -> Self {
+        ContentType {
+            mime: mime.to_ascii_lowercase(),
+            params: Vec::new(),
+        }
+    }
+
+    pub fn param(mut self, name: &str, value: &str) -> Self {
+        self.params.push((name.to_ascii_lowercase(), value.to_string()));
+        self
+    }
+
+    pub fn get_param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.get_param("charset")
+    }
+}
+
+impl Header for ContentType {
+    const NAME: &'static str = "Content-Type";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        let mut parts = value.split(';').map(str::trim);
+        let mime = parts.next().unwrap_or_default();
+        match mime.split_once('/') {
+            Some((kind, subtype)) if !kind.is_empty() && !subtype.is_empty() => {}
+            _ => return Err(HeaderError::Malformed),
+        }
+
+        let mut content_type = ContentType::new(mime);
+        for part in parts.filter(|part| !part.is_empty()) {
+            let (name, value) = part.split_once('=').ok_or(HeaderError::Malformed)?;
+            content_type = content_type.param(name.trim(), unquote(value.trim()));
+        }
+        Ok(content_type)
+    }
+
+    fn encode(&self) -> String {
+        let mut value = self.mime.clone();
+        for (name, param) in &self.params {
+            if !param.is_empty() && param.bytes().all(crate::tokens::is_header_name_token) {
+                let _ = write!(value, "; {}={}", name, param);
+            } else {
+                let _ = write!(
+                    value,
+                    "; {}=\"{}\"",
+                    name,
+                    param.replace('\\', "\\\\").replace('"', "\\\"")
+                );
+            }
+        }
+        value
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentLength(pub u64);
+
+impl Header for ContentLength {
+    const NAME: &'static str = "Content-Length";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        decode_number(value).map(ContentLength)
+    }
+
+    fn encode(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+/// Codings applied to the body, in the order they were applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentEncoding(pub Vec<String>);
+
+impl Header for ContentEncoding {
+    const NAME: &'static str = "Content-Encoding";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        decode_list(value).map(ContentEncoding)
+    }
+
+    fn encode(&self) -> String {
+        self.0.join(", ")
+    }
+}
+
+/// An element of an `Accept*` list with its `q` weight, 1.0 when absent. Other parameters stay part of
+/// `value`, e.g. `text/html;level=1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityValue {
+    pub value: String,
+    pub quality: f32,
+}
+
+impl QualityValue {
+    pub fn new(value: &str, quality: f32) -> Self {
+        QualityValue {
+            value: value.to_string(),
+            quality,
+        }
+    }
+}
+
+fn decode_quality_list(value: &str) -> Result<Vec<QualityValue>, HeaderError> {
+    let mut items = Vec::new();
+    for item in split_list(value) {
+        let mut parts = item.split(';').map(str::trim);
+        let mut value = parts.next().unwrap_or_default().to_string();
+        let mut quality = 1.0;
+        for param in parts {
+            match param.split_once('=') {
+                Some((name, q)) if name.trim().eq_ignore_ascii_case("q") => {
+                    quality = q.trim().parse().map_err(|_| HeaderError::Malformed)?;
+                    if !(0.0..=1.0).contains(&quality) {
+                        return Err(HeaderError::Malformed);
+                    }
+                }
+                _ => {
+                    value.push(';');
+                    value.push_str(param);
+                }
+            }
+        }
+        items.push(QualityValue { value, quality });
+    }
+    Ok(items)
+}
+
+fn encode_quality_list(items: &[QualityValue]) -> String {
+    let items: Vec<String> = items
+        .iter()
+        .map(|item| match item.quality {
+            1.0 => item.value.clone(),
+            quality => format!("{};q={}", item.value, quality),
+        })
+        .collect();
+    items.join(", ")
+}
+
+/// Sorts by descending weight, keeping the original order among equal weights.
+fn by_preference(items: &[QualityValue]) -> Vec<&QualityValue> {
+    let mut sorted: Vec<&QualityValue> = items.iter().filter(|item| item.quality > 0.0).collect();
+    sorted.sort_by(|a, b| b.quality.total_cmp(&a.quality));
+    sorted
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accept(pub Vec<QualityValue>);
+
+impl Accept {
+    /// Acceptable media types, most preferred first, without those weighted `q=0`.
+    pub fn preferred(&self) -> Vec<&QualityValue> {
+        by_preference(&self.0)
+    }
+}
+
+impl Header for Accept {
+    const NAME: &'static str = "Accept";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        decode_quality_list(value).map(Accept)
+    }
+
+    fn encode(&self) -> String {
+        encode_quality_list(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptEncoding(pub Vec<QualityValue>);
+
+impl AcceptEncoding {
+    /// Acceptable codings, most preferred first, without those weighted `q=0`.
+    pub fn preferred(&self) -> Vec<&QualityValue> {
+        by_preference(&self.0)
+    }
+}
+
+impl Header for AcceptEncoding {
+    const NAME: &'static str = "Accept-Encoding";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        decode_quality_list(value).map(AcceptEncoding)
+    }
+
+    fn encode(&self) -> String {
+        encode_quality_list(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptLanguage(pub Vec<QualityValue>);
+
+impl AcceptLanguage {
+    /// Acceptable languages, most preferred first, without those weighted `q=0`.
+    pub fn preferred(&self) -> Vec<&QualityValue> {
+        by_preference(&self.0)
+    }
+}
+
+impl Header for AcceptLanguage {
+    const NAME: &'static str = "Accept-Language";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        decode_quality_list(value).map(AcceptLanguage)
+    }
+
+    fn encode(&self) -> String {
+        encode_quality_list(&self.0)
+    }
+}
+
+/// The common `Cache-Control` directives. Unknown directives are ignored when decoding.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub no_cache: bool,
+    pub no_store: bool,
+    pub no_transform: bool,
+    pub must_revalidate: bool,
+    pub public: bool,
+    pub private: bool,
+    pub immutable: bool,
+    pub max_age: Option<u64>,
+    pub s_maxage: Option<u64>,
+}
+
+impl Header for CacheControl {
+    const NAME: &'static str = "Cache-Control";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        let mut cache_control = CacheControl::default();
+        for directive in split_list(value) {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(unquote(argument.trim()))),
+                None => (directive, None),
+            };
+            let seconds = || argument.ok_or(HeaderError::Malformed).and_then(decode_number);
+            match name.to_ascii_lowercase().as_str() {
+                "no-cache" => cache_control.no_cache = true,
+                "no-store" => cache_control.no_store = true,
+                "no-transform" => cache_control.no_transform = true,
+                "must-revalidate" => cache_control.must_revalidate = true,
+                "public" => cache_control.public = true,
+                "private" => cache_control.private = true,
+                "immutable" => cache_control.immutable = true,
+                "max-age" => cache_control.max_age = Some(seconds()?),
+                "s-maxage" => cache_control.s_maxage = Some(seconds()?),
+                _ => {}
+            }
+        }
+        Ok(cache_control)
+    }
+
+    fn encode(&self) -> String {
+        let mut directives = Vec::new();
+        let flags = [
+            (self.no_cache, "no-cache"),
+            (self.no_store, "no-store"),
+            (self.no_transform, "no-transform"),
+            (self.must_revalidate, "must-revalidate"),
+            (self.public, "public"),
+            (self.private, "private"),
+            (self.immutable, "immutable"),
+        ];
+        directives.extend(flags.iter().filter(|(set, _)| *set).map(|(_, name)| name.to_string()));
+        if let Some(max_age) = self.max_age {
+            directives.push(format!("max-age={}", max_age));
+        }
+        if let Some(s_maxage) = self.s_maxage {
+            directives.push(format!("s-maxage={}", s_maxage));
+        }
+        directives.join(", ")
+    }
+}
+
+/// An entity tag, `"xyz"` or weak `W/"xyz"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntityTag {
+    pub weak: bool,
+    pub tag: String,
+}
+
+impl EntityTag {
+    pub fn strong(tag: &str) -> Self {
+        EntityTag {
+            weak: false,
+            tag: tag.to_string(),
+        }
+    }
+
+    pub fn weak(tag: &str) -> Self {
+        EntityTag {
+            weak: true,
+            tag: tag.to_string(),
+        }
+    }
+
+    /// Strong comparison: both tags are strong and equal.
+    pub fn strong_eq(&self, other: &EntityTag) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Weak comparison: the tags are equal, whether weak or not.
+    pub fn weak_eq(&self, other: &EntityTag) -> bool {
+        self.tag == other.tag
+    }
+
+    /// Parses one tag at the start of `value` and returns the rest.
+    fn parse(value: &str) -> Result<(EntityTag, &str), HeaderError> {
+        let (weak, value) = match value.strip_prefix("W/") {
+            Some(value) => (true, value),
+            None => (false, value),
+        };
+        let value = value.strip_prefix('"').ok_or(HeaderError::Malformed)?;
+        let end = value.find('"').ok_or(HeaderError::Malformed)?;
+        let tag = &value[..end];
+        if !tag
+            .bytes()
+            .all(|b| b == 0x21 || (0x23..=0x7e).contains(&b) || b >= 0x80)
+        {
+            return Err(HeaderError::Malformed);
+        }
+        let etag = EntityTag {
+            weak,
+            tag: tag.to_string(),
+        };
+        Ok((etag, &value[end + 1..]))
+    }
+
+    fn decode_list(value: &str) -> Result<Vec<EntityTag>, HeaderError> {
+        let mut tags = Vec::new();
+        let mut rest = value.trim_start_matches([' ', '\t', ',']);
+        while !rest.is_empty() {
+            let (tag, after) = EntityTag::parse(rest)?;
+            tags.push(tag);
+            let after = after.trim_start_matches([' ', '\t']);
+            if !after.is_empty() && !after.starts_with(',') {
+                return Err(HeaderError::Malformed);
+            }
+            rest = after.trim_start_matches([' ', '\t', ',']);
+        }
+        Ok(tags)
+    }
+}
+
+impl std::fmt::Display for EntityTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.weak {
+            write!(f, "W/")?;
+        }
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ETag(pub EntityTag);
+
+impl Header for ETag {
+    const NAME: &'static str = "ETag";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        match EntityTag::parse(value.trim())? {
+            (tag, "") => Ok(ETag(tag)),
+            _ => Err(HeaderError::Malformed),
+        }
+    }
+
+    fn encode(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+/// Shared shape of `If-Match` and `If-None-Match`: `*` or a list of tags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ETagMatch {
+    Any,
+    Tags(Vec<EntityTag>),
+}
+
+impl ETagMatch {
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        match value.trim() {
+            "*" => Ok(ETagMatch::Any),
+            value => match EntityTag::decode_list(value)? {
+                tags if tags.is_empty() => Err(HeaderError::Malformed),
+                tags => Ok(ETagMatch::Tags(tags)),
+            },
+        }
+    }
+
+    fn encode(&self) -> String {
+        match self {
+            ETagMatch::Any => "*".to_string(),
+            ETagMatch::Tags(tags) => tags.iter().map(EntityTag::to_string).collect::<Vec<_>>().join(", "),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfMatch(pub ETagMatch);
+
+impl IfMatch {
+    /// Whether the current representation's tag satisfies the condition, using strong comparison.
+    pub fn matches(&self, current: Option<&EntityTag>) -> bool {
+        match (&self.0, current) {
+            (ETagMatch::Any, current) => current.is_some(),
+            (ETagMatch::Tags(tags), Some(current)) => tags.iter().any(|tag| tag.strong_eq(current)),
+            (ETagMatch::Tags(_), None) => false,
+        }
+    }
+}
+
+impl Header for IfMatch {
+    const NAME: &'static str = "If-Match";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        ETagMatch::decode(value).map(IfMatch)
+    }
+
+    fn encode(&self) -> String {
+        self.0.encode()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IfNoneMatch(pub ETagMatch);
+
+impl IfNoneMatch {
+    /// Whether the client's cached copy is current, i.e. a `GET` can be answered with 304. Uses weak
+    /// comparison.
+    pub fn matches(&self, current: Option<&EntityTag>) -> bool {
+        match (&self.0, current) {
+            (ETagMatch::Any, current) => current.is_some(),
+            (ETagMatch::Tags(tags), Some(current)) => tags.iter().any(|tag| tag.weak_eq(current)),
+            (ETagMatch::Tags(_), None) => false,
+        }
+    }
+}
+
+impl Header for IfNoneMatch {
+    const NAME: &'static str = "If-None-Match";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        ETagMatch::decode(value).map(IfNoneMatch)
+    }
+
+    fn encode(&self) -> String {
+        self.0.encode()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `first-last`, both inclusive.
+    FromTo(u64, u64),
+    /// `first-`, to the end.
+    From(u64),
+    /// `-length`, the last `length` bytes.
+    Last(u64),
+}
+
+impl ByteRange {
+    /// The inclusive `(first, last)` offsets for a representation of `length` bytes, or `None` if the
+    /// range is not satisfiable.
+    pub fn resolve(&self, length: u64) -> Option<(u64, u64)> {
+        match *self {
+            ByteRange::FromTo(first, last) if first < length => Some((first, last.min(length - 1))),
+            ByteRange::From(first) if first < length => Some((first, length - 1)),
+            ByteRange::Last(suffix) if suffix > 0 && length > 0 => Some((length.saturating_sub(suffix), length - 1)),
+            _ => None,
+        }
+    }
+}
+
+/// A `bytes` range request. Other range units are rejected when decoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Range(pub Vec<ByteRange>);
+
+impl Header for Range {
+    const NAME: &'static str = "Range";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        let (unit, ranges) = value.trim().split_once('=').ok_or(HeaderError::Malformed)?;
+        if !unit.eq_ignore_ascii_case("bytes") {
+            return Err(HeaderError::Malformed);
+        }
+
+        let mut byte_ranges = Vec::new();
+        for range in split_list(ranges) {
+            let (first, last) = range.split_once('-').ok_or(HeaderError::Malformed)?;
+            let byte_range = match (first.trim(), last.trim()) {
+                ("", "") => return Err(HeaderError::Malformed),
+                ("", last) => ByteRange::Last(decode_number(last)?),
+                (first, "") => ByteRange::From(decode_number(first)?),
+                (first, last) => {
+                    let (first, last) = (decode_number(first)?, decode_number(last)?);
+                    if first > last {
+                        return Err(HeaderError::Malformed);
+                    }
+                    ByteRange::FromTo(first, last)
+                }
+            };
+            byte_ranges.push(byte_range);
+        }
+        if byte_ranges.is_empty() {
+            return Err(HeaderError::Malformed);
+        }
+        Ok(Range(byte_ranges))
+    }
+
+    fn encode(&self) -> String {
+        let ranges: Vec<String> = self
+            .0
+            .iter()
+            .map(|range| match range {
+                ByteRange::FromTo(first, last) => format!("{}-{}", first, last),
+                ByteRange::From(first) => format!("{}-", first),
+                ByteRange::Last(length) => format!("-{}", length),
+            })
+            .collect();
+        format!("bytes={}", ranges.join(", "))
+    }
+}
+
+/// `bytes first-last/length`. `range` is `None` for the `*/length` form of a 416 response, and
+/// `complete_length` is `None` when the length is unknown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRange {
+    pub range: Option<(u64, u64)>,
+    pub complete_length: Option<u64>,
+}
+
+impl Header for ContentRange {
+    const NAME: &'static str = "Content-Range";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        let (unit, rest) = value.trim().split_once(' ').ok_or(HeaderError::Malformed)?;
+        if !unit.eq_ignore_ascii_case("bytes") {
+            return Err(HeaderError::Malformed);
+        }
+        let (range, length) = rest.trim().split_once('/').ok_or(HeaderError::Malformed)?;
+        let range = match range {
+            "*" => None,
+            range => {
+                let (first, last) = range.split_once('-').ok_or(HeaderError::Malformed)?;
+                let (first, last) = (decode_number(first)?, decode_number(last)?);
+                if first > last {
+                    return Err(HeaderError::Malformed);
+                }
+                Some((first, last))
+            }
+        };
+        let complete_length = match length {
+            "*" => None,
+            length => Some(decode_number(length)?),
+        };
+        if range.is_none() && complete_length.is_none() {
+            return Err(HeaderError::Malformed);
+        }
+        Ok(ContentRange { range, complete_length })
+    }
+
+    fn encode(&self) -> String {
+        let range = match self.range {
+            Some((first, last)) => format!("{}-{}", first, last),
+            None => "*".to_string(),
+        };
+        let length = match self.complete_length {
+            Some(length) => length.to_string(),
+            None => "*".to_string(),
+        };
+        format!("bytes {}/{}", range, length)
+    }
+}
+
+/// `host[:port]`. IPv6 hosts keep their brackets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl Header for Host {
+    const NAME: &'static str = "Host";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        let value = value.trim();
+        let (host, port) = match value.rfind(':') {
+            Some(colon) if !value[colon..].contains(']') => (&value[..colon], Some(&value[colon + 1..])),
+            _ => (value, None),
+        };
+        let valid = |b: u8| b.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=%[]:".contains(&b);
+        if host.is_empty() || !host.bytes().all(valid) {
+            return Err(HeaderError::Malformed);
+        }
+        // RFC 3986 section 3.2.3: an empty port is the same as no port.
+        let port = match port {
+            Some("") | None => None,
+            Some(port) => Some(port.parse().map_err(|_| HeaderError::Malformed)?),
+        };
+        Ok(Host {
+            host: host.to_string(),
+            port,
+        })
+    }
+
+    fn encode(&self) -> String {
+        match self.port {
+            Some(port) => format!("{}:{}", self.host, port),
+            None => self.host.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserAgent(pub String);
+
+impl Header for UserAgent {
+    const NAME: &'static str = "User-Agent";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        decode_text(value).map(UserAgent)
+    }
+
+    fn encode(&self) -> String {
+        self.0.clone()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Referer(pub String);
+
+impl Header for Referer {
+    const NAME: &'static str = "Referer";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        decode_text(value).map(Referer)
+    }
+
+    fn encode(&self) -> String {
+        self.0.clone()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location(pub String);
+
+impl Header for Location {
+    const NAME: &'static str = "Location";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        decode_text(value).map(Location)
+    }
+
+    fn encode(&self) -> String {
+        self.0.clone()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Origin(pub String);
+
+impl Header for Origin {
+    const NAME: &'static str = "Origin";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        decode_text(value).map(Origin)
+    }
+
+    fn encode(&self) -> String {
+        self.0.clone()
+    }
+}
+
+/// Connection options, e.g. `close` or `keep-alive`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Connection(pub Vec<String>);
+
+impl Connection {
+    pub fn close() -> Self {
+        Connection(vec!["close".to_string()])
+    }
+
+    pub fn keep_alive() -> Self {
+        Connection(vec!["keep-alive".to_string()])
+    }
+
+    pub fn contains(&self, option: &str) -> bool {
+        self.0.iter().any(|item| item.eq_ignore_ascii_case(option))
+    }
+}
+
+impl Header for Connection {
+    const NAME: &'static str = "Connection";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        decode_list(value).map(Connection)
+    }
+
+    fn encode(&self) -> String {
+        self.0.join(", ")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vary(pub Vec<String>);
+
+impl Header for Vary {
+    const NAME: &'static str = "Vary";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        decode_list(value).map(Vary)
+    }
+
+    fn encode(&self) -> String {
+        self.0.join(", ")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Allow(pub Vec<Method>);
+
+impl Header for Allow {
+    const NAME: &'static str = "Allow";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        split_list(value)
+            .map(|method| Method::try_from(method).map_err(|_| HeaderError::Malformed))
+            .collect::<Result<_, _>>()
+            .map(Allow)
+    }
+
+    fn encode(&self) -> String {
+        self.0.iter().map(Method::to_string).collect::<Vec<_>>().join(", ")
+    }
+}
+
+/// Delay in seconds. The HTTP-date form is not supported and fails to decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryAfter(pub u64);
+
+impl Header for RetryAfter {
+    const NAME: &'static str = "Retry-After";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        decode_number(value).map(RetryAfter)
+    }
+
+    fn encode(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Authorization(pub Credentials);
+
+impl Header for Authorization {
+    const NAME: &'static str = "Authorization";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        Credentials::parse(value)
+            .map(Authorization)
+            .ok_or(HeaderError::Malformed)
+    }
+
+    fn encode(&self) -> String {
+        self.0.encode()
+    }
+}
+
+/// Request cookies as name/value pairs, in the order sent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie(pub Vec<(String, String)>);
+
+impl Cookie {
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl Header for Cookie {
+    const NAME: &'static str = "Cookie";
+
+    fn decode(value: &str) -> Result<Self, HeaderError> {
+        // Repeated fields are joined with ", " before decoding, so accept that as a separator too.
+        let mut cookies = Vec::new();
+        for pair in value.split([';', ',']).map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').ok_or(HeaderError::Malformed)?;
+            if name.trim().is_empty() {
+                return Err(HeaderError::Malformed);
+            }
+            cookies.push((name.trim().to_string(), unquote(value.trim()).to_string()));
+        }
+        Ok(Cookie(cookies))
+    }
+
+    fn encode(&self) -> String {
+        let pairs: Vec<String> = self
+            .0
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        pairs.join("; ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn round_trip<H: Header + PartialEq + std::fmt::Debug>(value: &str, expected: H) {
+        let decoded = H::decode(value).expect("decoding header");
+        assert_eq!(decoded, expected);
+        assert_eq!(H::decode(&decoded.encode()).expect("decoding encoded header"), expected);
+    }
+
+    #[test]
+    fn content_type() {
+        let expected = ContentType::new("text/html").param("charset", "utf-8");
+        round_trip("Text/HTML; Charset=\"utf-8\"", expected.clone());
+        assert_eq!(expected.encode(), "text/html; charset=utf-8");
+        assert_eq!(expected.charset(), Some("utf-8"));
+        assert!(ContentType::decode("texthtml").is_err());
+    }
+
+    #[test]
+    fn accept_orders_by_quality() {
+        let accept = Accept::decode("text/html;level=1, application/json;q=0.9, */*;q=0.1, image/png;q=0").unwrap();
+        let preferred: Vec<&str> = accept.preferred().iter().map(|item| item.value.as_str()).collect();
+        assert_eq!(preferred, ["text/html;level=1", "application/json", "*/*"]);
+        assert_eq!(
+            accept.encode(),
+            "text/html;level=1, application/json;q=0.9, */*;q=0.1, image/png;q=0"
+        );
+        assert!(AcceptEncoding::decode("gzip;q=2").is_err());
+    }
+
+    #[test]
+    fn cache_control() {
+        let cache_control = CacheControl {
+            public: true,
+            max_age: Some(3600),
+            ..Default::default()
+        };
+        round_trip("public, max-age=\"3600\", community=\"UCI\"", cache_control.clone());
+        assert_eq!(cache_control.encode(), "public, max-age=3600");
+        assert!(CacheControl::decode("max-age").is_err());
+    }
+
+    #[test]
+    fn entity_tags() {
+        round_trip("W/\"v1\"", ETag(EntityTag::weak("v1")));
+        assert!(ETag::decode("v1").is_err());
+
+        let if_none_match = IfNoneMatch::decode("\"a,b\", W/\"c\"").unwrap();
+        assert_eq!(
+            if_none_match,
+            IfNoneMatch(ETagMatch::Tags(vec![EntityTag::strong("a,b"), EntityTag::weak("c")]))
+        );
+        assert!(if_none_match.matches(Some(&EntityTag::strong("c"))));
+        assert!(!if_none_match.matches(None));
+        assert!(IfNoneMatch::decode("*").unwrap().matches(Some(&EntityTag::strong("x"))));
+
+        let if_match = IfMatch::decode("W/\"c\"").unwrap();
+        assert!(!if_match.matches(Some(&EntityTag::strong("c"))));
+    }
+
+    #[test]
+    fn ranges() {
+        let range = Range(vec![
+            ByteRange::FromTo(0, 499),
+            ByteRange::From(9500),
+            ByteRange::Last(500),
+        ]);
+        round_trip("bytes=0-499, 9500-,-500", range.clone());
+        assert_eq!(range.0[0].resolve(100), Some((0, 99)));
+        assert_eq!(range.0[1].resolve(100), None);
+        assert_eq!(range.0[2].resolve(100), Some((0, 99)));
+        assert!(Range::decode("bytes=5-1").is_err());
+        assert!(Range::decode("items=0-1").is_err());
+
+        round_trip(
+            "bytes 0-499/1234",
+            ContentRange {
+                range: Some((0, 499)),
+                complete_length: Some(1234),
+            },
+        );
+        round_trip(
+            "bytes */1234",
+            ContentRange {
+                range: None,
+                complete_length: Some(1234),
+            },
+        );
+    }
+
+    #[rstest]
+    #[case("example.com", "example.com", None)]
+    #[case("example.com:8080", "example.com", Some(8080))]
+    #[case("[::1]:80", "[::1]", Some(80))]
+    #[case("[::1]", "[::1]", None)]
+    fn host_and_port(#[case] value: &str, #[case] host: &str, #[case] port: Option<u16>) {
+        round_trip(
+            value,
+            Host {
+                host: host.to_string(),
+                port,
+            },
+        );
+    }
+
+    #[test]
+    fn empty_port_is_no_port() {
+        let host = Host::decode("example.com:").unwrap();
+        assert_eq!(host.host, "example.com");
+        assert_eq!(host.port, None);
+        assert_eq!(Host::decode("[::1]:").unwrap().port, None);
+    }
+
+    #[test]
+    fn lists_and_values() {
+        round_trip(
+            "close, Upgrade",
+            Connection(vec!["close".to_string(), "Upgrade".to_string()]),
+        );
+        round_trip("GET, POST", Allow(vec![Method::GET, Method::POST]));
+        round_trip("120", RetryAfter(120));
+        assert!(RetryAfter::decode("Wed, 21 Oct 2015 07:28:00 GMT").is_err());
+        round_trip("gzip", ContentEncoding(vec!["gzip".to_string()]));
+        round_trip(
+            "Basic dXNlcjpwYXNz",
+            Authorization(Credentials::Basic {
+                username: "user".to_string(),
+                password: "pass".to_string(),
+            }),
+        );
+
+        let cookie = Cookie::decode("session=abc; theme=\"dark\"").unwrap();
+        assert_eq!(cookie.get("theme"), Some("dark"));
+        assert_eq!(cookie.encode(), "session=abc; theme=dark");
+    }
+}
@@ -1,14 +1,26 @@
 mod context;
 mod error;
+mod extensions;
+mod headers;
+mod middleware;
+mod panic;
 mod request;
 mod response;
 mod router;
 mod server;
+#[cfg(feature = "tower")]
+mod service;
 mod tokens;
+mod uri;
 pub use context::*;
 pub use error::*;
+pub use extensions::*;
+pub use headers::*;
 pub use httpstatus::{StatusClass, StatusCode};
+pub use middleware::*;
+pub use panic::HandlerPanic;
 pub use request::*;
 pub use response::*;
 pub use router::*;
 pub use server::*;
+pub use uri::*;
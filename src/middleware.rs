@@ -0,0 +1,186 @@
+mod auth;
+#[cfg(any(feature = "brotli", feature = "deflate", feature = "gzip"))]
+mod compression;
+mod cors;
+#[cfg(any(feature = "deflate", feature = "gzip"))]
+mod decompression;
+mod logger;
+mod rate_limit;
+mod request_id;
+mod timeout;
+
+pub use auth::*;
+#[cfg(any(feature = "brotli", feature = "deflate", feature = "gzip"))]
+pub use compression::*;
+pub use cors::*;
+#[cfg(any(feature = "deflate", feature = "gzip"))]
+pub use decompression::*;
+pub use logger::*;
+pub use rate_limit::*;
+pub use request_id::*;
+pub use timeout::*;
+
+use std::{future::Future, net::SocketAddr, sync::Arc};
+
+use crate::{Context, Extensions, Handler, HandlerFuture, RequestError, Response};
+
+/// A layer that runs around a handler. It can inspect or modify the request before calling
+/// `next.run(ctx)`, modify the response afterwards, or skip `next` entirely to answer on its own.
+///
+/// Implemented for any `async fn(&mut Context, Next<'_>)`.
+pub trait Middleware: Send + Sync {
+    fn handle<'a>(&'a self, ctx: &'a mut Context, next: Next<'a>) -> HandlerFuture<'a>;
+
+    /// Called instead of [`Middleware::handle`] when the server answers a connection itself because its
+    /// request could not be read, parsed or was for a host that is not allowed. Only middleware added with
+    /// [`crate::Server::middleware`] is called, in the order it was added. Does nothing by default.
+    fn rejected(&self, _rejection: &mut Rejection) {}
+}
+
+/// A request the server rejected before it reached the middleware chain, see [`Middleware::rejected`].
+pub struct Rejection {
+    pub error: RequestError,
+    pub remote_addr: SocketAddr,
+    /// The response about to be sent, with the error status and `Connection: close`.
+    pub response: Response,
+    pub extensions: Extensions,
+}
+
+/// See [`crate::HandlerFn`].
+pub trait MiddlewareFn<'a>: Send + Sync {
+    type Future: Future<Output = ()> + Send + 'a;
+
+    fn invoke(&self, ctx: &'a mut Context, next: Next<'a>) -> Self::Future;
+}
+
+impl<'a, F, Fut> MiddlewareFn<'a> for F
+where
+    F: Fn(&'a mut Context, Next<'a>) -> Fut + Send + Sync,
+    Fut: Future<Output = ()> + Send + 'a,
+{
+    type Future = Fut;
+
+    fn invoke(&self, ctx: &'a mut Context, next: Next<'a>) -> Self::Future {
+        self(ctx, next)
+    }
+}
+
+impl<F> Middleware for F
+where
+    F: for<'a> MiddlewareFn<'a>,
+{
+    fn handle<'a>(&'a self, ctx: &'a mut Context, next: Next<'a>) -> HandlerFuture<'a> {
+        Box::pin(self.invoke(ctx, next))
+    }
+}
+
+/// The remainder of a middleware chain, ending in the handler.
+pub struct Next<'a> {
+    middleware: &'a [Arc<dyn Middleware>],
+    endpoint: &'a dyn Handler,
+}
+
+impl<'a> Next<'a> {
+    pub fn new(middleware: &'a [Arc<dyn Middleware>], endpoint: &'a dyn Handler) -> Self {
+        Next { middleware, endpoint }
+    }
+
+    pub fn run(mut self, ctx: &'a mut Context) -> HandlerFuture<'a> {
+        match self.middleware.split_first() {
+            Some((current, rest)) => {
+                self.middleware = rest;
+                current.handle(ctx, self)
+            }
+            None => self.endpoint.call(ctx),
+        }
+    }
+}
+
+/// Adds `tokens` to the `Vary` header, keeping the ones the handler or other middleware already set.
+pub(crate) fn add_vary(response: &mut Response, tokens: &[&str]) {
+    let mut vary = response.get_header("Vary").unwrap_or_default().to_string();
+    for token in tokens {
+        if vary
+            .split(',')
+            .any(|existing| existing.trim().eq_ignore_ascii_case(token))
+        {
+            continue;
+        }
+        if !vary.is_empty() {
+            vary.push_str(", ");
+        }
+        vary.push_str(token);
+    }
+    response.set_header("Vary", &vary);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Request, Response};
+    use bytes::Bytes;
+    use httpstatus::StatusCode;
+
+    fn context() -> Context {
+        let request = Request::new(Bytes::from_static(b"GET /test HTTP/1.1\r\nHost: localhost\r\n\r\n"))
+            .expect("parsing request");
+        Context::new(request, Response::new())
+    }
+
+    async fn handler(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"handler;");
+    }
+
+    async fn first(ctx: &mut Context, next: Next<'_>) {
+        ctx.response_mut().write_body(b"first-before;");
+        next.run(ctx).await;
+        ctx.response_mut().write_body(b"first-after;");
+    }
+
+    async fn second(ctx: &mut Context, next: Next<'_>) {
+        ctx.response_mut().write_body(b"second-before;");
+        next.run(ctx).await;
+        ctx.response_mut().write_body(b"second-after;");
+    }
+
+    async fn short_circuit(ctx: &mut Context, _next: Next<'_>) {
+        ctx.response_mut().status_code(StatusCode::Unauthorized);
+        ctx.response_mut().write_body(b"denied;");
+    }
+
+    #[tokio::test]
+    async fn runs_in_order_around_handler() {
+        let middleware: Vec<Arc<dyn Middleware>> = vec![Arc::new(first), Arc::new(second)];
+        let mut ctx = context();
+        Next::new(&middleware, &handler).run(&mut ctx).await;
+
+        assert_eq!(
+            ctx.response().body(),
+            b"first-before;second-before;handler;second-after;first-after;"
+        );
+    }
+
+    #[tokio::test]
+    async fn can_short_circuit() {
+        let middleware: Vec<Arc<dyn Middleware>> = vec![Arc::new(first), Arc::new(short_circuit), Arc::new(second)];
+        let mut ctx = context();
+        Next::new(&middleware, &handler).run(&mut ctx).await;
+
+        assert_eq!(ctx.response().status(), &StatusCode::Unauthorized);
+        assert_eq!(ctx.response().body(), b"first-before;denied;first-after;");
+    }
+
+    #[test]
+    fn add_vary_matches_whole_tokens() {
+        let mut response = Response::new();
+        add_vary(&mut response, &["Accept-Encoding"]);
+        assert_eq!(response.get_header("Vary"), Some("Accept-Encoding"));
+
+        response.set_header("Vary", "X-Accept-Encoding-Hint, origin");
+        add_vary(&mut response, &["Origin", "Accept-Encoding"]);
+        assert_eq!(
+            response.get_header("Vary"),
+            Some("X-Accept-Encoding-Hint, origin, Accept-Encoding")
+        );
+    }
+}
@@ -0,0 +1,321 @@
+use std::future::Future;
+
+use httpstatus::StatusCode;
+
+use crate::{response::validate_header, Context, HandlerFuture, Middleware, Next};
+
+/// Credentials parsed from an `Authorization` header.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Credentials {
+    Basic { username: String, password: String },
+    Bearer(String),
+}
+
+impl Credentials {
+    pub fn parse(authorization: &str) -> Option<Credentials> {
+        let (scheme, value) = authorization.trim().split_once(' ')?;
+        let value = value.trim();
+        if scheme.eq_ignore_ascii_case("basic") {
+            let decoded = String::from_utf8(decode_base64(value)?).ok()?;
+            let (username, password) = decoded.split_once(':')?;
+            Some(Credentials::Basic {
+                username: username.to_string(),
+                password: password.to_string(),
+            })
+        } else if scheme.eq_ignore_ascii_case("bearer") && !value.is_empty() {
+            Some(Credentials::Bearer(value.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// The `Authorization` header value for these credentials.
+    pub fn encode(&self) -> String {
+        match self {
+            Credentials::Basic { username, password } => {
+                format!(
+                    "Basic {}",
+                    encode_base64(format!("{}:{}", username, password).as_bytes())
+                )
+            }
+            Credentials::Bearer(token) => format!("Bearer {}", token),
+        }
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_base64(input: &[u8]) -> String {
+    let mut output = String::with_capacity(input.len().div_ceil(3) * 4);
+    for chunk in input.chunks(3) {
+        let mut buffer = [0; 3];
+        buffer[..chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes([0, buffer[0], buffer[1], buffer[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                output.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                output.push('=');
+            }
+        }
+    }
+    output
+}
+
+fn decode_base64(input: &str) -> Option<Vec<u8>> {
+    fn value(ch: u8) -> Option<u32> {
+        match ch {
+            b'A'..=b'Z' => Some((ch - b'A') as u32),
+            b'a'..=b'z' => Some((ch - b'a' + 26) as u32),
+            b'0'..=b'9' => Some((ch - b'0' + 52) as u32),
+            b'+' => Some(62),
+            b'/' => Some(63),
+            _ => None,
+        }
+    }
+
+    let input = input.trim_end_matches('=').as_bytes();
+    if input.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    for chunk in input.chunks(4) {
+        let mut buffer = 0;
+        for (i, ch) in chunk.iter().enumerate() {
+            buffer |= value(*ch)? << (18 - 6 * i);
+        }
+        let bytes = buffer.to_be_bytes();
+        output.extend_from_slice(&bytes[1..chunk.len()]);
+    }
+    Some(output)
+}
+
+/// Middleware that checks `Authorization` credentials with an async validator. When the validator
+/// returns a principal it is stored on the context, retrievable with `ctx.get::<P>()`; otherwise the
+/// request is answered with 401 and a `WWW-Authenticate` challenge.
+pub struct Auth<F> {
+    validator: F,
+    realm: String,
+    basic: bool,
+    bearer: bool,
+}
+
+impl<F> Auth<F> {
+    /// Accepts both Basic and Bearer credentials.
+    pub fn new(validator: F) -> Self {
+        Auth {
+            validator,
+            realm: "Restricted".to_string(),
+            basic: true,
+            bearer: true,
+        }
+    }
+
+    pub fn basic(validator: F) -> Self {
+        Auth {
+            bearer: false,
+            ..Auth::new(validator)
+        }
+    }
+
+    pub fn bearer(validator: F) -> Self {
+        Auth {
+            basic: false,
+            ..Auth::new(validator)
+        }
+    }
+
+    /// # Panics
+    ///
+    /// If `realm` contains CR, LF or another control character.
+    pub fn realm(mut self, realm: &str) -> Self {
+        if let Err(e) = validate_header("WWW-Authenticate", realm) {
+            panic!("invalid realm {:?}: {}", realm, e);
+        }
+        self.realm = realm.to_string();
+        self
+    }
+
+    fn accepts(&self, credentials: &Credentials) -> bool {
+        match credentials {
+            Credentials::Basic { .. } => self.basic,
+            Credentials::Bearer(_) => self.bearer,
+        }
+    }
+
+    fn challenge(&self, rejected: Option<&Credentials>) -> String {
+        let realm = self.realm.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut challenges = Vec::new();
+        if self.basic {
+            challenges.push(format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm));
+        }
+        if self.bearer {
+            match rejected {
+                Some(Credentials::Bearer(_)) => {
+                    challenges.push(format!("Bearer realm=\"{}\", error=\"invalid_token\"", realm))
+                }
+                _ => challenges.push(format!("Bearer realm=\"{}\"", realm)),
+            }
+        }
+        challenges.join(", ")
+    }
+}
+
+impl<F, Fut, P> Middleware for Auth<F>
+where
+    F: Fn(Credentials) -> Fut + Send + Sync,
+    Fut: Future<Output = Option<P>> + Send,
+    P: Send + Sync + 'static,
+{
+    fn handle<'a>(&'a self, ctx: &'a mut Context, next: Next<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let credentials = ctx
+                .request()
+                .headers
+                .get_str("Authorization")
+                .ok()
+                .and_then(|authorization| Credentials::parse(&authorization))
+                .filter(|credentials| self.accepts(credentials));
+
+            let rejected = match credentials {
+                Some(credentials) => match (self.validator)(credentials.clone()).await {
+                    Some(principal) => {
+                        ctx.insert(principal);
+                        return next.run(ctx).await;
+                    }
+                    None => Some(credentials),
+                },
+                None => None,
+            };
+
+            let challenge = self.challenge(rejected.as_ref());
+            let response = ctx.response_mut();
+            response.status_code(StatusCode::Unauthorized);
+            response.set_header("WWW-Authenticate", &challenge);
+            response.write_body(b"Unauthorized");
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Request, Response};
+    use bytes::Bytes;
+
+    #[derive(Debug, PartialEq)]
+    struct User(String);
+
+    async fn validate(credentials: Credentials) -> Option<User> {
+        match credentials {
+            Credentials::Basic { username, password } if password == "secret" => Some(User(username)),
+            Credentials::Bearer(token) if token == "token-123" => Some(User("service".to_string())),
+            _ => None,
+        }
+    }
+
+    async fn handler(ctx: &mut Context) {
+        let name = ctx.get::<User>().map(|user| user.0.clone()).unwrap_or_default();
+        ctx.response_mut().write_body(name.as_bytes());
+    }
+
+    async fn run<F, Fut>(auth: &Auth<F>, raw: &'static [u8]) -> Response
+    where
+        F: Fn(Credentials) -> Fut + Send + Sync,
+        Fut: Future<Output = Option<User>> + Send,
+    {
+        let request = Request::new(Bytes::from_static(raw)).expect("parsing request");
+        let mut ctx = Context::new(request, Response::new());
+        auth.handle(&mut ctx, Next::new(&[], &handler)).await;
+        ctx.into_response()
+    }
+
+    #[test]
+    fn parse_credentials() {
+        assert_eq!(
+            Credentials::parse("Basic YWxhZGRpbjpvcGVuc2VzYW1l"),
+            Some(Credentials::Basic {
+                username: "aladdin".to_string(),
+                password: "opensesame".to_string()
+            })
+        );
+        assert_eq!(
+            Credentials::parse("bearer abc.def"),
+            Some(Credentials::Bearer("abc.def".to_string()))
+        );
+        assert_eq!(Credentials::parse("Basic !!!"), None);
+        assert_eq!(Credentials::parse("Digest abc"), None);
+        assert_eq!(Credentials::parse("Bearer"), None);
+    }
+
+    #[test]
+    fn decode_base64_padding() {
+        assert_eq!(decode_base64("YQ==").as_deref(), Some(&b"a"[..]));
+        assert_eq!(decode_base64("YWI=").as_deref(), Some(&b"ab"[..]));
+        assert_eq!(decode_base64("YWJj").as_deref(), Some(&b"abc"[..]));
+        assert_eq!(decode_base64("Y"), None);
+        for input in [&b"a"[..], b"ab", b"abc", b"user:p\xc3\xa4ss"] {
+            assert_eq!(decode_base64(&encode_base64(input)).as_deref(), Some(input));
+        }
+        assert_eq!(encode_base64(b"ab"), "YWI=");
+    }
+
+    #[tokio::test]
+    async fn basic_inserts_principal() {
+        // alice:secret
+        let response = run(
+            &Auth::basic(validate),
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nAuthorization: Basic YWxpY2U6c2VjcmV0\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.status(), &StatusCode::Ok);
+        assert_eq!(response.body(), b"alice");
+    }
+
+    #[tokio::test]
+    async fn missing_credentials_are_challenged() {
+        let response = run(
+            &Auth::basic(validate).realm("admin"),
+            b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.status(), &StatusCode::Unauthorized);
+        assert_eq!(
+            response.get_header("WWW-Authenticate"),
+            Some("Basic realm=\"admin\", charset=\"UTF-8\"")
+        );
+    }
+
+    #[tokio::test]
+    async fn bearer() {
+        let auth = Auth::bearer(validate).realm("api");
+
+        let response = run(
+            &auth,
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer token-123\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.body(), b"service");
+
+        let response = run(
+            &auth,
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer wrong\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.status(), &StatusCode::Unauthorized);
+        assert_eq!(
+            response.get_header("WWW-Authenticate"),
+            Some("Bearer realm=\"api\", error=\"invalid_token\"")
+        );
+
+        // basic credentials are not accepted by a bearer-only layer
+        let response = run(
+            &auth,
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nAuthorization: Basic YWxpY2U6c2VjcmV0\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.status(), &StatusCode::Unauthorized);
+        assert_eq!(response.get_header("WWW-Authenticate"), Some("Bearer realm=\"api\""));
+    }
+}
@@ -0,0 +1,305 @@
+use std::io::{self, Write};
+
+use httpstatus::StatusCode;
+
+use super::add_vary;
+use crate::{Context, HandlerFuture, Method, Middleware, Next};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    #[cfg(feature = "brotli")]
+    Brotli,
+    #[cfg(feature = "gzip")]
+    Gzip,
+    #[cfg(feature = "deflate")]
+    Deflate,
+}
+
+impl Encoding {
+    /// Enabled encodings, in the order preferred when the client ranks several equally.
+    const ALL: &'static [Encoding] = &[
+        #[cfg(feature = "brotli")]
+        Encoding::Brotli,
+        #[cfg(feature = "gzip")]
+        Encoding::Gzip,
+        #[cfg(feature = "deflate")]
+        Encoding::Deflate,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => "br",
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => "gzip",
+            #[cfg(feature = "deflate")]
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    pub fn encode(&self, body: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+                encoder.write_all(body)?;
+                encoder.flush()?;
+                Ok(encoder.into_inner())
+            }
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            #[cfg(feature = "deflate")]
+            Encoding::Deflate => {
+                let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+
+    /// Picks the encoding with the highest q-value in an `Accept-Encoding` header, ignoring `q=0`.
+    /// `*` matches any encoding not listed explicitly.
+    pub fn negotiate(accept_encoding: &str) -> Option<Encoding> {
+        let mut preferences = Vec::new();
+        for item in accept_encoding.split(',') {
+            let mut params = item.split(';');
+            let name = params.next().unwrap_or_default().trim().to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q=").or(param.trim().strip_prefix("Q=")))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if !name.is_empty() {
+                preferences.push((name, quality));
+            }
+        }
+
+        let quality_of = |encoding: &Encoding| {
+            let named = preferences
+                .iter()
+                .find(|(name, _)| name == encoding.as_str() || (name == "x-gzip" && encoding.as_str() == "gzip"));
+            let wildcard = preferences.iter().find(|(name, _)| name == "*");
+            named.or(wildcard).map_or(0.0, |(_, quality)| *quality)
+        };
+
+        Encoding::ALL
+            .iter()
+            .map(|encoding| (*encoding, quality_of(encoding)))
+            .filter(|(_, quality)| *quality > 0.0)
+            .fold(None, |best: Option<(Encoding, f32)>, (encoding, quality)| match best {
+                Some((_, best_quality)) if best_quality >= quality => best,
+                _ => Some((encoding, quality)),
+            })
+            .map(|(encoding, _)| encoding)
+    }
+}
+
+/// Middleware that compresses response bodies with the best encoding the client accepts, for
+/// compressible content types above a minimum size.
+///
+/// Bodies are fully buffered, so `Content-Length` is always the compressed length.
+pub struct Compression {
+    min_size: usize,
+    content_types: Vec<String>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::new()
+    }
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Compression {
+            min_size: 1024,
+            content_types: [
+                "text/",
+                "application/json",
+                "application/javascript",
+                "application/xml",
+                "application/wasm",
+                "image/svg+xml",
+            ]
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        }
+    }
+
+    /// Bodies smaller than this are sent as is. Defaults to 1024 bytes.
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Content type prefixes eligible for compression, replacing the defaults.
+    pub fn content_types<'a>(mut self, content_types: impl IntoIterator<Item = &'a str>) -> Self {
+        self.content_types = content_types.into_iter().map(str::to_string).collect();
+        self
+    }
+
+    fn is_compressible(&self, content_type: &str) -> bool {
+        let content_type = content_type.trim().to_ascii_lowercase();
+        self.content_types
+            .iter()
+            .any(|prefix| content_type.starts_with(prefix.as_str()))
+    }
+}
+
+impl Middleware for Compression {
+    fn handle<'a>(&'a self, ctx: &'a mut Context, next: Next<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let accept_encoding = ctx
+                .request()
+                .headers
+                .get_combined("Accept-Encoding")
+                .ok()
+                .and_then(|value| String::from_utf8(value.to_vec()).ok());
+            let is_head = ctx.request().method == Method::HEAD;
+
+            next.run(ctx).await;
+
+            let response = ctx.response_mut();
+            if !self.is_compressible(response.get_content_type()) {
+                return;
+            }
+            add_vary(response, &["Accept-Encoding"]);
+
+            let status = response.status();
+            if is_head
+                || response.body().len() < self.min_size
+                || response.get_header("Content-Encoding").is_some()
+                || matches!(status, StatusCode::NoContent | StatusCode::NotModified)
+            {
+                return;
+            }
+            let Some(encoding) = accept_encoding.as_deref().and_then(Encoding::negotiate) else {
+                return;
+            };
+
+            match encoding.encode(response.body()) {
+                Ok(body) => {
+                    response.clear();
+                    response.write_body(&body);
+                    response.set_header("Content-Encoding", encoding.as_str());
+                }
+                Err(e) => eprintln!("Error compressing response: {}", e),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Request, Response};
+    use bytes::Bytes;
+    use std::io::Read;
+
+    async fn handler(ctx: &mut Context) {
+        ctx.response_mut().write_body(&b"hello world ".repeat(200));
+    }
+
+    async fn run(compression: &Compression, raw: &'static [u8]) -> Response {
+        let request = Request::new(Bytes::from_static(raw)).expect("parsing request");
+        let mut ctx = Context::new(request, Response::new());
+        compression.handle(&mut ctx, Next::new(&[], &handler)).await;
+        ctx.into_response()
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn negotiate_q_values() {
+        assert_eq!(Encoding::negotiate("gzip"), Some(Encoding::Gzip));
+        assert_eq!(Encoding::negotiate("identity"), None);
+        assert_eq!(Encoding::negotiate("gzip;q=0"), None);
+        assert_eq!(Encoding::negotiate("*;q=0"), None);
+        assert_eq!(Encoding::negotiate("unknown, gzip;q=0.5"), Some(Encoding::Gzip));
+        #[cfg(feature = "deflate")]
+        assert_eq!(
+            Encoding::negotiate("gzip;q=0.5, deflate;q=0.8"),
+            Some(Encoding::Deflate)
+        );
+        #[cfg(feature = "brotli")]
+        {
+            assert_eq!(Encoding::negotiate("gzip, br"), Some(Encoding::Brotli));
+            assert_eq!(Encoding::negotiate("*"), Some(Encoding::Brotli));
+            assert_eq!(Encoding::negotiate("br;q=0, *"), Some(Encoding::Gzip));
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn compress_gzip() {
+        let response = run(
+            &Compression::new(),
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.get_header("Content-Encoding"), Some("gzip"));
+        assert_eq!(response.get_header("Vary"), Some("Accept-Encoding"));
+
+        let mut body = Vec::new();
+        flate2::read::GzDecoder::new(response.body())
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(body, b"hello world ".repeat(200));
+    }
+
+    #[cfg(feature = "deflate")]
+    #[tokio::test]
+    async fn compress_deflate() {
+        let response = run(
+            &Compression::new(),
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: deflate\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.get_header("Content-Encoding"), Some("deflate"));
+
+        let mut body = Vec::new();
+        flate2::read::ZlibDecoder::new(response.body())
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(body, b"hello world ".repeat(200));
+    }
+
+    #[cfg(feature = "brotli")]
+    #[tokio::test]
+    async fn compress_brotli() {
+        let response = run(
+            &Compression::new(),
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: br\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.get_header("Content-Encoding"), Some("br"));
+
+        let mut body = Vec::new();
+        brotli::Decompressor::new(response.body(), 4096)
+            .read_to_end(&mut body)
+            .unwrap();
+        assert_eq!(body, b"hello world ".repeat(200));
+    }
+
+    #[tokio::test]
+    async fn skip_small_or_ineligible_bodies() {
+        let response = run(
+            &Compression::new().min_size(10_000),
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip, deflate, br\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.get_header("Content-Encoding"), None);
+        assert_eq!(response.get_header("Vary"), Some("Accept-Encoding"));
+
+        let response = run(
+            &Compression::new().content_types(["application/json"]),
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip, deflate, br\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.get_header("Content-Encoding"), None);
+        assert_eq!(response.body(), b"hello world ".repeat(200));
+    }
+}
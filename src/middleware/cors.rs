@@ -0,0 +1,393 @@
+use std::{sync::Arc, time::Duration};
+
+use httpstatus::StatusCode;
+
+use super::add_vary;
+use crate::{response::validate_header, Context, HandlerFuture, Method, Middleware, Next};
+
+/// Origins a [`Cors`] layer accepts.
+#[derive(Clone)]
+pub enum AllowOrigin {
+    Any,
+    Exact(String),
+    List(Vec<String>),
+    Predicate(Arc<dyn Fn(&str) -> bool + Send + Sync>),
+}
+
+impl AllowOrigin {
+    pub fn predicate(predicate: impl Fn(&str) -> bool + Send + Sync + 'static) -> Self {
+        AllowOrigin::Predicate(Arc::new(predicate))
+    }
+
+    fn allows(&self, origin: &str) -> bool {
+        match self {
+            AllowOrigin::Any => true,
+            AllowOrigin::Exact(allowed) => allowed == origin,
+            AllowOrigin::List(allowed) => allowed.iter().any(|allowed| allowed == origin),
+            AllowOrigin::Predicate(predicate) => predicate(origin),
+        }
+    }
+}
+
+/// Middleware that adds `Access-Control-*` headers for allowed origins and answers preflight
+/// `OPTIONS` requests itself, without calling the handler.
+#[derive(Clone)]
+pub struct Cors {
+    allow_origin: AllowOrigin,
+    allow_methods: Vec<Method>,
+    allow_headers: Option<Vec<String>>,
+    allow_credentials: bool,
+    expose_headers: Vec<String>,
+    max_age: Option<Duration>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors::new()
+    }
+}
+
+impl Cors {
+    /// Allows any origin with `GET`, `HEAD` and `POST`, and mirrors the requested headers on preflight.
+    pub fn new() -> Self {
+        Cors {
+            allow_origin: AllowOrigin::Any,
+            allow_methods: vec![Method::GET, Method::HEAD, Method::POST],
+            allow_headers: None,
+            allow_credentials: false,
+            expose_headers: Vec::new(),
+            max_age: None,
+        }
+    }
+
+    pub fn allow_origin(mut self, allow_origin: AllowOrigin) -> Self {
+        self.allow_origin = allow_origin;
+        self
+    }
+
+    pub fn allow_methods(mut self, methods: impl IntoIterator<Item = Method>) -> Self {
+        self.allow_methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Restricts the request headers allowed on preflight. Without this, the requested headers are mirrored.
+    ///
+    /// # Panics
+    ///
+    /// If a name is not a valid header name.
+    pub fn allow_headers<'a>(mut self, headers: impl IntoIterator<Item = &'a str>) -> Self {
+        self.allow_headers = Some(header_names(headers));
+        self
+    }
+
+    pub fn allow_credentials(mut self, allow_credentials: bool) -> Self {
+        self.allow_credentials = allow_credentials;
+        self
+    }
+
+    /// # Panics
+    ///
+    /// If a name is not a valid header name.
+    pub fn expose_headers<'a>(mut self, headers: impl IntoIterator<Item = &'a str>) -> Self {
+        self.expose_headers = header_names(headers);
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// `*` is only sent for any-origin without credentials, browsers reject it otherwise.
+    fn origin_header(&self, origin: &str) -> String {
+        match self.allow_origin {
+            AllowOrigin::Any if !self.allow_credentials => "*".to_string(),
+            _ => origin.to_string(),
+        }
+    }
+
+    fn preflight(&self, ctx: &mut Context, origin: &str) {
+        let request_headers = ctx.request().headers.get_str("Access-Control-Request-Headers").ok();
+        let request_method = ctx
+            .request()
+            .headers
+            .get_str("Access-Control-Request-Method")
+            .ok()
+            .and_then(|method| Method::try_from(method.as_str()).ok());
+
+        let response = ctx.response_mut();
+        add_vary(
+            response,
+            &[
+                "Origin",
+                "Access-Control-Request-Method",
+                "Access-Control-Request-Headers",
+            ],
+        );
+
+        let method_allowed = request_method.is_some_and(|method| self.allow_methods.contains(&method));
+        if !self.allow_origin.allows(origin) || !method_allowed {
+            response.status_code(StatusCode::Forbidden);
+            return;
+        }
+
+        // The origin and requested headers come from the request, so they are set with the checked setters.
+        if response
+            .try_set_header("Access-Control-Allow-Origin", &self.origin_header(origin))
+            .is_err()
+        {
+            response.status_code(StatusCode::Forbidden);
+            return;
+        }
+        response.status_code(StatusCode::NoContent);
+        let methods: Vec<String> = self.allow_methods.iter().map(Method::to_string).collect();
+        response.set_header("Access-Control-Allow-Methods", &methods.join(", "));
+
+        let allow_headers = match &self.allow_headers {
+            Some(headers) => Some(headers.join(", ")),
+            None => request_headers,
+        };
+        if let Some(headers) = allow_headers.filter(|headers| !headers.is_empty()) {
+            let _ = response.try_set_header("Access-Control-Allow-Headers", &headers);
+        }
+        if self.allow_credentials {
+            response.set_header("Access-Control-Allow-Credentials", "true");
+        }
+        if let Some(max_age) = self.max_age {
+            response.set_header("Access-Control-Max-Age", &max_age.as_secs().to_string());
+        }
+    }
+
+    fn simple(&self, ctx: &mut Context, origin: &str) {
+        let response = ctx.response_mut();
+        if !matches!(self.allow_origin, AllowOrigin::Any) || self.allow_credentials {
+            add_vary(response, &["Origin"]);
+        }
+        if !self.allow_origin.allows(origin) {
+            return;
+        }
+
+        if response
+            .try_set_header("Access-Control-Allow-Origin", &self.origin_header(origin))
+            .is_err()
+        {
+            return;
+        }
+        if self.allow_credentials {
+            response.set_header("Access-Control-Allow-Credentials", "true");
+        }
+        if !self.expose_headers.is_empty() {
+            response.set_header("Access-Control-Expose-Headers", &self.expose_headers.join(", "));
+        }
+    }
+}
+
+impl Middleware for Cors {
+    fn handle<'a>(&'a self, ctx: &'a mut Context, next: Next<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let Ok(origin) = ctx.request().headers.get_str("Origin") else {
+                return next.run(ctx).await;
+            };
+
+            let is_preflight = ctx.request().method == Method::OPTIONS
+                && ctx.request().headers.get("Access-Control-Request-Method").is_ok();
+            if is_preflight {
+                return self.preflight(ctx, &origin);
+            }
+
+            next.run(ctx).await;
+            self.simple(ctx, &origin);
+        })
+    }
+}
+
+fn header_names<'a>(headers: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    headers
+        .into_iter()
+        .map(|header| match validate_header(header, "") {
+            Ok(()) => header.to_string(),
+            Err(e) => panic!("invalid header name {:?}: {}", header, e),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Request, Response};
+    use bytes::Bytes;
+
+    async fn handler(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"handler");
+    }
+
+    async fn run(cors: &Cors, raw: &'static [u8]) -> Response {
+        let request = Request::new(Bytes::from_static(raw)).expect("parsing request");
+        let mut ctx = Context::new(request, Response::new());
+        cors.handle(&mut ctx, Next::new(&[], &handler)).await;
+        ctx.into_response()
+    }
+
+    fn header(response: &Response, key: &str) -> Option<String> {
+        let built = String::from_utf8(response.build()).unwrap();
+        let prefix = format!("{}: ", key);
+        built
+            .lines()
+            .find_map(|line| line.strip_prefix(&prefix).map(str::to_string))
+    }
+
+    #[tokio::test]
+    async fn without_origin_passes_through() {
+        let response = run(&Cors::new(), b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert_eq!(response.body(), b"handler");
+        assert_eq!(header(&response, "Access-Control-Allow-Origin"), None);
+    }
+
+    #[tokio::test]
+    async fn simple_request_any_origin() {
+        let cors = Cors::new().expose_headers(["X-Total"]);
+        let response = run(
+            &cors,
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.body(), b"handler");
+        assert_eq!(header(&response, "Access-Control-Allow-Origin").as_deref(), Some("*"));
+        assert_eq!(
+            header(&response, "Access-Control-Expose-Headers").as_deref(),
+            Some("X-Total")
+        );
+    }
+
+    #[tokio::test]
+    async fn credentials_echo_origin() {
+        let cors = Cors::new()
+            .allow_origin(AllowOrigin::List(vec!["https://app.example".to_string()]))
+            .allow_credentials(true);
+
+        let response = run(
+            &cors,
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\n\r\n",
+        )
+        .await;
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Origin").as_deref(),
+            Some("https://app.example")
+        );
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Credentials").as_deref(),
+            Some("true")
+        );
+        assert_eq!(header(&response, "Vary").as_deref(), Some("Origin"));
+
+        let response = run(
+            &cors,
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://evil.example\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.body(), b"handler");
+        assert_eq!(header(&response, "Access-Control-Allow-Origin"), None);
+    }
+
+    #[tokio::test]
+    async fn preflight_is_answered_without_handler() {
+        let cors = Cors::new()
+            .allow_origin(AllowOrigin::predicate(|origin| origin.ends_with(".example")))
+            .allow_methods([Method::GET, Method::PUT])
+            .allow_headers(["Content-Type"])
+            .max_age(Duration::from_secs(600));
+
+        let response = run(
+            &cors,
+            b"OPTIONS /items HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\nAccess-Control-Request-Method: PUT\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.status(), &StatusCode::NoContent);
+        assert_eq!(response.body(), b"");
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Origin").as_deref(),
+            Some("https://app.example")
+        );
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Methods").as_deref(),
+            Some("GET, PUT")
+        );
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Headers").as_deref(),
+            Some("Content-Type")
+        );
+        assert_eq!(header(&response, "Access-Control-Max-Age").as_deref(), Some("600"));
+    }
+
+    #[tokio::test]
+    async fn preflight_rejects_disallowed_method_or_origin() {
+        let cors = Cors::new().allow_origin(AllowOrigin::Exact("https://app.example".to_string()));
+
+        let response = run(
+            &cors,
+            b"OPTIONS / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\nAccess-Control-Request-Method: DELETE\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.status(), &StatusCode::Forbidden);
+
+        let response = run(
+            &cors,
+            b"OPTIONS / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://evil.example\r\nAccess-Control-Request-Method: GET\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.status(), &StatusCode::Forbidden);
+        assert_eq!(header(&response, "Access-Control-Allow-Origin"), None);
+    }
+
+    #[tokio::test]
+    async fn preflight_mirrors_requested_headers() {
+        let response = run(
+            &Cors::new(),
+            b"OPTIONS / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\nAccess-Control-Request-Method: POST\r\nAccess-Control-Request-Headers: X-Token\r\n\r\n",
+        )
+        .await;
+        assert_eq!(response.status(), &StatusCode::NoContent);
+        assert_eq!(
+            header(&response, "Access-Control-Allow-Headers").as_deref(),
+            Some("X-Token")
+        );
+    }
+
+    #[tokio::test]
+    async fn vary_keeps_existing_tokens() {
+        async fn varies(ctx: &mut Context) {
+            ctx.response_mut().set_header("Vary", "Cookie, origin");
+        }
+
+        let cors = Cors::new().allow_origin(AllowOrigin::Exact("https://app.example".to_string()));
+        let request = Request::new(Bytes::from_static(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\n\r\n",
+        ))
+        .expect("parsing request");
+        let mut ctx = Context::new(request, Response::new());
+        cors.handle(&mut ctx, Next::new(&[], &varies)).await;
+        assert_eq!(ctx.response().get_header("Vary"), Some("Cookie, origin"));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn stacks_with_compression() {
+        async fn text(ctx: &mut Context) {
+            let response = ctx.response_mut();
+            response.content_type("text/plain");
+            response.write_body(&b"hello ".repeat(300));
+        }
+
+        let cors = Cors::new().allow_origin(AllowOrigin::Exact("https://app.example".to_string()));
+        let middleware: Vec<Arc<dyn Middleware>> = vec![Arc::new(crate::Compression::new())];
+        let request = Request::new(Bytes::from_static(
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nOrigin: https://app.example\r\nAccept-Encoding: gzip\r\n\r\n",
+        ))
+        .expect("parsing request");
+        let mut ctx = Context::new(request, Response::new());
+        cors.handle(&mut ctx, Next::new(&middleware, &text)).await;
+        let response = ctx.into_response();
+        assert_eq!(response.get_header("Content-Encoding"), Some("gzip"));
+        assert_eq!(response.get_header("Vary"), Some("Accept-Encoding, Origin"));
+    }
+}
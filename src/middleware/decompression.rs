@@ -0,0 +1,197 @@
+use std::io::{self, Read};
+
+use httpstatus::StatusCode;
+
+use crate::{Context, HandlerFuture, Middleware, Next};
+
+/// Middleware that decodes request bodies sent with `Content-Encoding: gzip` or `deflate`, so handlers
+/// always see the plain body. Other encodings are answered with 415, and bodies that would decode past
+/// `max_size` with 413.
+pub struct Decompression {
+    max_size: usize,
+}
+
+impl Default for Decompression {
+    fn default() -> Self {
+        Decompression::new()
+    }
+}
+
+const ACCEPTED: &str = if cfg!(all(feature = "gzip", feature = "deflate")) {
+    "gzip, deflate"
+} else if cfg!(feature = "gzip") {
+    "gzip"
+} else {
+    "deflate"
+};
+
+enum DecodeError {
+    Unsupported,
+    TooLarge,
+    Invalid(io::Error),
+}
+
+impl Decompression {
+    pub fn new() -> Self {
+        Decompression {
+            max_size: 10 * 1024 * 1024,
+        }
+    }
+
+    /// Largest decoded body accepted. Defaults to 10 MiB.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    fn decode(&self, encoding: &str, body: &[u8]) -> Result<Vec<u8>, DecodeError> {
+        let limit = self.max_size as u64 + 1;
+        let mut decoded = Vec::new();
+        let result = match encoding {
+            #[cfg(feature = "gzip")]
+            "gzip" | "x-gzip" => flate2::read::MultiGzDecoder::new(body)
+                .take(limit)
+                .read_to_end(&mut decoded),
+            #[cfg(feature = "deflate")]
+            "deflate" => flate2::read::ZlibDecoder::new(body)
+                .take(limit)
+                .read_to_end(&mut decoded),
+            _ => return Err(DecodeError::Unsupported),
+        };
+
+        match result {
+            Ok(_) if decoded.len() > self.max_size => Err(DecodeError::TooLarge),
+            Ok(_) => Ok(decoded),
+            Err(e) => Err(DecodeError::Invalid(e)),
+        }
+    }
+
+    /// Encodings are listed in the order they were applied, so they are undone in reverse.
+    fn decompress(&self, ctx: &mut Context, content_encoding: &str) -> Result<(), DecodeError> {
+        let mut body = std::mem::take(&mut ctx.request_mut().body);
+        for encoding in content_encoding.rsplit(',') {
+            let encoding = encoding.trim().to_ascii_lowercase();
+            if encoding.is_empty() || encoding == "identity" {
+                continue;
+            }
+            body = self.decode(&encoding, &body)?.into();
+        }
+
+        let request = ctx.request_mut();
+        request.headers.remove("Content-Encoding");
+        request
+            .headers
+            .insert("Content-Length", body.len().to_string().into_bytes());
+        request.body = body;
+        Ok(())
+    }
+}
+
+impl Middleware for Decompression {
+    fn handle<'a>(&'a self, ctx: &'a mut Context, next: Next<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let Ok(content_encoding) = ctx.request().headers.get_str("Content-Encoding") else {
+                return next.run(ctx).await;
+            };
+
+            let (status, message): (StatusCode, &[u8]) = match self.decompress(ctx, &content_encoding) {
+                Ok(()) => return next.run(ctx).await,
+                Err(DecodeError::Unsupported) => (StatusCode::UnsupportedMediaType, b"Unsupported Content-Encoding"),
+                Err(DecodeError::TooLarge) => (StatusCode::PayloadTooLarge, b"Payload Too Large"),
+                Err(DecodeError::Invalid(e)) => {
+                    eprintln!("Error decompressing request body: {}", e);
+                    (StatusCode::BadRequest, b"Invalid request body encoding")
+                }
+            };
+
+            let response = ctx.response_mut();
+            response.status_code(status);
+            response.write_body(message);
+            if matches!(response.status(), StatusCode::UnsupportedMediaType) {
+                response.set_header("Accept-Encoding", ACCEPTED);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Request, Response};
+    use bytes::Bytes;
+    use std::io::Write;
+
+    async fn echo(ctx: &mut Context) {
+        let body = ctx.request().body.clone();
+        ctx.response_mut().write_body(&body);
+    }
+
+    async fn run(decompression: &Decompression, encoding: &str, body: &[u8]) -> Response {
+        let mut raw = format!(
+            "POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Encoding: {}\r\nContent-Length: {}\r\n\r\n",
+            encoding,
+            body.len()
+        )
+        .into_bytes();
+        raw.extend_from_slice(body);
+
+        let request = Request::new(Bytes::from(raw)).expect("parsing request");
+        let mut ctx = Context::new(request, Response::new());
+        decompression.handle(&mut ctx, Next::new(&[], &echo)).await;
+        ctx.into_response()
+    }
+
+    #[cfg(feature = "gzip")]
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn decompress_gzip() {
+        let response = run(&Decompression::new(), "gzip", &gzip(b"{\"hello\":\"world\"}")).await;
+        assert_eq!(response.status(), &StatusCode::Ok);
+        assert_eq!(response.body(), b"{\"hello\":\"world\"}");
+    }
+
+    #[cfg(feature = "deflate")]
+    #[tokio::test]
+    async fn decompress_deflate() {
+        let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"deflated").unwrap();
+        let body = encoder.finish().unwrap();
+
+        let response = run(&Decompression::new(), "deflate", &body).await;
+        assert_eq!(response.body(), b"deflated");
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn reject_over_limit() {
+        let body = gzip(&[0; 64 * 1024]);
+        let response = run(&Decompression::new().max_size(1024), "gzip", &body).await;
+        assert_eq!(response.status(), &StatusCode::PayloadTooLarge);
+    }
+
+    #[cfg(feature = "gzip")]
+    #[tokio::test]
+    async fn reject_corrupt_body() {
+        let response = run(&Decompression::new(), "gzip", b"not gzip").await;
+        assert_eq!(response.status(), &StatusCode::BadRequest);
+    }
+
+    #[tokio::test]
+    async fn reject_unsupported_encoding() {
+        let response = run(&Decompression::new(), "zstd", b"data").await;
+        assert_eq!(response.status(), &StatusCode::UnsupportedMediaType);
+        assert_eq!(response.get_header("Accept-Encoding"), Some(ACCEPTED));
+    }
+
+    #[tokio::test]
+    async fn identity_passes_through() {
+        let response = run(&Decompression::new(), "identity", b"plain").await;
+        assert_eq!(response.body(), b"plain");
+    }
+}
@@ -0,0 +1,529 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, SyncSender, TrySendError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Context, HandlerFuture, Middleware, Next, Rejection, RequestId, Version};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// `host ident authuser [date] "request" status bytes`
+    Common,
+    /// Common Log Format followed by `"referer" "user-agent"`.
+    Combined,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Middleware that writes one line per request, to stdout by default.
+///
+/// When a [`RequestId`] is set, Common and Combined lines end with the quoted ID and JSON lines
+/// carry it as `request_id`.
+///
+/// Lines are written and files rotated on a dedicated thread, so a slow disk does not stall the async
+/// workers. Up to 8192 lines are queued; past that, lines are dropped and the count is reported on stderr.
+/// Dropping the `AccessLog` writes out the queue before returning. When added with [`crate::Server::middleware`], requests the server rejects
+/// before routing, such as malformed ones, are logged too, with `-` as the request line.
+pub struct AccessLog {
+    format: LogFormat,
+    sender: Option<SyncSender<String>>,
+    dropped: Arc<AtomicU64>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AccessLog {
+    pub fn new(format: LogFormat) -> Self {
+        AccessLog::writer(format, io::stdout())
+    }
+
+    pub fn file(format: LogFormat, file: RotatingFile) -> Self {
+        AccessLog::writer(format, file)
+    }
+
+    pub fn writer(format: LogFormat, mut writer: impl Write + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<String>(8192);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let thread_dropped = dropped.clone();
+        let thread = thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || {
+                for line in receiver {
+                    if let Err(e) = writer.write_all(line.as_bytes()).and_then(|_| writer.flush()) {
+                        eprintln!("Error writing access log: {}", e);
+                    }
+                    let dropped = thread_dropped.swap(0, Ordering::Relaxed);
+                    if dropped > 0 {
+                        eprintln!("Access log fell behind, dropped {} lines", dropped);
+                    }
+                }
+            })
+            .expect("spawning access log thread");
+
+        AccessLog {
+            format,
+            sender: Some(sender),
+            dropped,
+            thread: Some(thread),
+        }
+    }
+
+    fn write(&self, entry: &LogEntry) {
+        let mut line = match self.format {
+            LogFormat::Common => entry.common(),
+            LogFormat::Combined => entry.combined(),
+            LogFormat::Json => entry.json(),
+        };
+        if let (Some(id), LogFormat::Common | LogFormat::Combined) = (&entry.request_id, self.format) {
+            line.push_str(&format!(" \"{}\"", escape(id)));
+        }
+        line.push('\n');
+
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        if let Err(TrySendError::Full(_)) = sender.try_send(line) {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for AccessLog {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Middleware for AccessLog {
+    fn handle<'a>(&'a self, ctx: &'a mut Context, next: Next<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let request = ctx.request();
+            let mut entry = LogEntry {
+                time: SystemTime::now(),
+                remote_addr: request.remote_addr,
+                method: request.method.to_string(),
+                path: request.target.to_string(),
+                version: request.version,
+                user_agent: request.headers.get_str("User-Agent").ok(),
+                referer: request.headers.get_str("Referer").ok(),
+                status: 0,
+                size: 0,
+                duration: Duration::ZERO,
+                request_id: None,
+            };
+
+            next.run(ctx).await;
+
+            entry.status = ctx.response().status().as_u16();
+            entry.size = ctx.response().body().len();
+            entry.duration = start.elapsed();
+            entry.request_id = ctx.get::<RequestId>().map(RequestId::to_string);
+            self.write(&entry);
+        })
+    }
+
+    fn rejected(&self, rejection: &mut Rejection) {
+        self.write(&LogEntry {
+            time: SystemTime::now(),
+            remote_addr: Some(rejection.remote_addr),
+            method: String::new(),
+            path: String::new(),
+            version: Version::Http11,
+            user_agent: None,
+            referer: None,
+            status: rejection.response.status().as_u16(),
+            size: rejection.response.body().len(),
+            duration: Duration::ZERO,
+            request_id: rejection.extensions.get::<RequestId>().map(RequestId::to_string),
+        });
+    }
+}
+
+struct LogEntry {
+    time: SystemTime,
+    remote_addr: Option<SocketAddr>,
+    method: String,
+    path: String,
+    version: Version,
+    user_agent: Option<String>,
+    referer: Option<String>,
+    status: u16,
+    size: usize,
+    duration: Duration,
+    request_id: Option<String>,
+}
+
+impl LogEntry {
+    fn host(&self) -> String {
+        self.remote_addr.map_or("-".to_string(), |addr| addr.ip().to_string())
+    }
+
+    /// `-` for rejected requests, which have no usable request line.
+    fn request_line(&self) -> String {
+        if self.method.is_empty() {
+            return "-".to_string();
+        }
+        format!("{} {} {}", self.method, self.path, self.version)
+    }
+
+    fn common(&self) -> String {
+        let size = match self.size {
+            0 => "-".to_string(),
+            size => size.to_string(),
+        };
+        format!(
+            "{} - - [{}] \"{}\" {} {}",
+            self.host(),
+            clf_time(self.time),
+            escape(&self.request_line()),
+            self.status,
+            size
+        )
+    }
+
+    fn combined(&self) -> String {
+        format!(
+            "{} \"{}\" \"{}\"",
+            self.common(),
+            escape(self.referer.as_deref().unwrap_or("-")),
+            escape(self.user_agent.as_deref().unwrap_or("-"))
+        )
+    }
+
+    fn json(&self) -> String {
+        let optional = |value: &Option<String>| value.as_deref().map_or("null".to_string(), json_string);
+        format!(
+            "{{\"time\":{},\"remote_addr\":{},\"method\":{},\"path\":{},\"version\":{},\"status\":{},\"size\":{},\"duration_ms\":{:.3},\"user_agent\":{},\"referer\":{},\"request_id\":{}}}",
+            json_string(&rfc3339_time(self.time)),
+            self.remote_addr.map_or("null".to_string(), |addr| json_string(&addr.ip().to_string())),
+            json_string(&self.method),
+            json_string(&self.path),
+            json_string(self.version.as_str()),
+            self.status,
+            self.size,
+            self.duration.as_secs_f64() * 1000.0,
+            optional(&self.user_agent),
+            optional(&self.referer),
+            optional(&self.request_id),
+        )
+    }
+}
+
+/// Escapes `"` and `\` and replaces control characters, so a crafted header cannot forge log lines.
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            ch if ch.is_control() => escaped.push_str(&format!("\\x{:02x}", ch as u32)),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for ch in value.chars() {
+        match ch {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            ch if ch.is_control() => escaped.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => escaped.push(ch),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Splits a timestamp into UTC (year, month, day, hour, minute, second).
+fn utc(time: SystemTime) -> (i64, u32, u32, u64, u64, u64) {
+    let secs = time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let (days, rem) = ((secs / 86400) as i64, secs % 86400);
+
+    // Howard Hinnant's civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
+fn clf_time(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc(time);
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        hour,
+        minute,
+        second
+    )
+}
+
+fn rfc3339_time(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc(time);
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// A log file that is rotated once it would grow past `max_size` bytes. The current file is renamed to
+/// `<path>.1`, `<path>.1` to `<path>.2` and so on, keeping at most `max_files` old files.
+pub struct RotatingFile {
+    path: PathBuf,
+    max_size: u64,
+    max_files: usize,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    pub fn new(path: impl AsRef<Path>, max_size: u64) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            max_size,
+            max_files: 5,
+            file,
+            size,
+        })
+    }
+
+    pub fn max_files(mut self, max_files: usize) -> Self {
+        self.max_files = max_files;
+        self
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated(index);
+                if from.exists() {
+                    fs::rename(from, self.rotated(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Request, Response};
+    use bytes::Bytes;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Buffer {
+        fn contents(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    async fn handler(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"hello");
+    }
+
+    async fn log(format: LogFormat) -> String {
+        log_with_id(format, None).await
+    }
+
+    async fn log_with_id(format: LogFormat, id: Option<&str>) -> String {
+        let buffer = Buffer::default();
+        let logger = AccessLog::writer(format, buffer.clone());
+
+        let mut request = Request::new(Bytes::from_static(
+            b"GET /index.html HTTP/1.1\r\nHost: localhost\r\nUser-Agent: curl/8.0\r\nReferer: http://example.com/\r\n\r\n",
+        ))
+        .expect("parsing request");
+        request.remote_addr = Some("127.0.0.1:4000".parse().unwrap());
+        let mut ctx = Context::new(request, Response::new());
+        if let Some(id) = id {
+            ctx.insert(RequestId::new(id));
+        }
+        logger.handle(&mut ctx, Next::new(&[], &handler)).await;
+
+        drop(logger);
+        buffer.contents()
+    }
+
+    /// Blocks every write until the test drops the other end of the gate.
+    struct Gated(mpsc::Receiver<()>, Buffer);
+
+    impl Write for Gated {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let _ = self.0.recv();
+            self.1.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_writer_does_not_block_requests() {
+        let (gate, gated) = mpsc::channel();
+        let buffer = Buffer::default();
+        let logger = AccessLog::writer(LogFormat::Common, Gated(gated, buffer.clone()));
+
+        for _ in 0..3 {
+            let request = Request::new(Bytes::from_static(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n"))
+                .expect("parsing request");
+            let mut ctx = Context::new(request, Response::new());
+            logger.handle(&mut ctx, Next::new(&[], &handler)).await;
+        }
+        assert_eq!(buffer.contents(), "");
+
+        drop(gate);
+        drop(logger);
+        assert_eq!(buffer.contents().lines().count(), 3);
+    }
+
+    #[test]
+    fn format_times() {
+        let time = UNIX_EPOCH + Duration::from_secs(971_186_136);
+        assert_eq!(clf_time(time), "10/Oct/2000:13:55:36 +0000");
+        assert_eq!(rfc3339_time(time), "2000-10-10T13:55:36Z");
+        assert_eq!(
+            rfc3339_time(UNIX_EPOCH + Duration::from_secs(1_709_164_800)),
+            "2024-02-29T00:00:00Z"
+        );
+    }
+
+    #[tokio::test]
+    async fn common_log_format() {
+        let line = log(LogFormat::Common).await;
+        assert!(line.starts_with("127.0.0.1 - - ["), "{}", line);
+        assert!(line.ends_with("] \"GET /index.html HTTP/1.1\" 200 5\n"), "{}", line);
+    }
+
+    #[tokio::test]
+    async fn combined_log_format() {
+        let line = log(LogFormat::Combined).await;
+        assert!(
+            line.ends_with("\"GET /index.html HTTP/1.1\" 200 5 \"http://example.com/\" \"curl/8.0\"\n"),
+            "{}",
+            line
+        );
+    }
+
+    #[tokio::test]
+    async fn json_log_format() {
+        let line = log(LogFormat::Json).await;
+        assert!(line.starts_with("{\"time\":\""), "{}", line);
+        assert!(line.contains(",\"remote_addr\":\"127.0.0.1\",\"method\":\"GET\",\"path\":\"/index.html\",\"version\":\"HTTP/1.1\",\"status\":200,\"size\":5,"), "{}", line);
+        assert!(
+            line.ends_with(",\"user_agent\":\"curl/8.0\",\"referer\":\"http://example.com/\",\"request_id\":null}\n"),
+            "{}",
+            line
+        );
+    }
+
+    #[tokio::test]
+    async fn include_request_id() {
+        let line = log_with_id(LogFormat::Common, Some("abc-123")).await;
+        assert!(line.ends_with("\" 200 5 \"abc-123\"\n"), "{}", line);
+
+        let line = log_with_id(LogFormat::Json, Some("abc-123")).await;
+        assert!(line.ends_with(",\"request_id\":\"abc-123\"}\n"), "{}", line);
+    }
+
+    #[test]
+    fn escape_quotes_and_control_characters() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\x0ad");
+        assert_eq!(json_string("a\"b\nc"), "\"a\\\"b\\nc\"");
+    }
+
+    #[test]
+    fn rotate_by_size() {
+        let dir = std::env::temp_dir().join(format!("rhhtp-access-log-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+
+        let mut file = RotatingFile::new(&path, 10).unwrap().max_files(2);
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(fs::read_to_string(dir.join("access.log.1")).unwrap(), "third\n");
+        assert_eq!(fs::read_to_string(dir.join("access.log.2")).unwrap(), "second\n");
+        assert!(!dir.join("access.log.3").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use httpstatus::StatusCode;
+
+use crate::{Context, HandlerFuture, Middleware, Next};
+
+pub type KeyFn = Arc<dyn Fn(&Context) -> Option<String> + Send + Sync>;
+
+/// What requests are grouped by when counting against a [`RateLimit`].
+#[derive(Clone)]
+pub enum RateLimitKey {
+    /// The client IP address.
+    RemoteAddr,
+    /// The value of a request header, such as an API key.
+    Header(String),
+    Custom(KeyFn),
+}
+
+impl RateLimitKey {
+    fn extract(&self, ctx: &Context) -> Option<String> {
+        match self {
+            RateLimitKey::RemoteAddr => ctx.request().remote_addr.map(|addr| addr.ip().to_string()),
+            RateLimitKey::Header(name) => ctx.request().headers.get_str(name).ok(),
+            RateLimitKey::Custom(extract) => extract(ctx),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Decision {
+    allowed: bool,
+    remaining: u32,
+    /// Time until the full quota is available again.
+    reset: Duration,
+    retry_after: Duration,
+}
+
+/// Keys tracked before the first prune, see [`Buckets::prune_at`].
+const MIN_PRUNE: usize = 1024;
+/// How often a map holding [`RateLimit::max_keys`] keys is pruned at most.
+const FULL_PRUNE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Middleware that allows `limit` requests per `period` for each key, using the generic cell rate
+/// algorithm (GCRA). Requests over the limit are answered with 429 and `Retry-After`, and every
+/// response carries `RateLimit-Limit`, `RateLimit-Remaining` and `RateLimit-Reset`.
+///
+/// Each instance keeps its own counters, so attaching separate instances to different routers or
+/// groups gives them separate limits. Requests with no key share a single bucket, as do new keys
+/// while [`RateLimit::max_keys`] keys are being tracked.
+pub struct RateLimit {
+    limit: u32,
+    period: Duration,
+    key: RateLimitKey,
+    max_keys: usize,
+    state: Mutex<Buckets>,
+}
+
+#[derive(Default)]
+struct Buckets {
+    /// Theoretical arrival time of the next request for each key.
+    tats: HashMap<String, Instant>,
+    /// Size at which the map is pruned next, twice its size after the last prune, so the cost of pruning
+    /// is spread over the keys added in between.
+    prune_at: usize,
+    pruned: Option<Instant>,
+}
+
+impl Buckets {
+    /// Drops keys whose bucket has fully refilled, so idle clients do not accumulate.
+    fn prune(&mut self, now: Instant) {
+        self.tats.retain(|_, tat| *tat > now);
+        self.prune_at = (self.tats.len() * 2).max(MIN_PRUNE);
+        self.pruned = Some(now);
+    }
+}
+
+impl RateLimit {
+    pub fn new(limit: u32, period: Duration) -> Self {
+        RateLimit {
+            limit: limit.max(1),
+            period,
+            key: RateLimitKey::RemoteAddr,
+            max_keys: 100_000,
+            state: Mutex::new(Buckets {
+                prune_at: MIN_PRUNE,
+                ..Buckets::default()
+            }),
+        }
+    }
+
+    /// Caps how many keys are tracked at once, 100,000 by default. Once reached, and pruning idle keys
+    /// frees no room, requests with new keys share the bucket of requests with no key.
+    pub fn max_keys(mut self, max_keys: usize) -> Self {
+        self.max_keys = max_keys.max(1);
+        self
+    }
+
+    pub fn key(mut self, key: RateLimitKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    pub fn key_fn(self, extract: impl Fn(&Context) -> Option<String> + Send + Sync + 'static) -> Self {
+        self.key(RateLimitKey::Custom(Arc::new(extract)))
+    }
+
+    fn interval(&self) -> Duration {
+        self.period / self.limit
+    }
+
+    fn check(&self, key: &str, now: Instant) -> Decision {
+        let interval = self.interval();
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let mut key = key;
+        if !state.tats.contains_key(key) {
+            if state.tats.len() >= state.prune_at {
+                state.prune(now);
+            }
+            if state.tats.len() >= self.max_keys {
+                if state
+                    .pruned
+                    .is_none_or(|pruned| now.duration_since(pruned) >= FULL_PRUNE_INTERVAL)
+                {
+                    state.prune(now);
+                }
+                if state.tats.len() >= self.max_keys {
+                    key = "";
+                }
+            }
+        }
+
+        let tat = state.tats.get(key).copied().filter(|tat| *tat > now).unwrap_or(now);
+        let new_tat = tat + interval;
+        let wait = new_tat - now;
+
+        if wait > self.period {
+            let used = tat - now;
+            return Decision {
+                allowed: false,
+                remaining: 0,
+                reset: used,
+                retry_after: wait - self.period,
+            };
+        }
+
+        state.tats.insert(key.to_string(), new_tat);
+        let remaining = (self.period - wait).as_nanos() / interval.as_nanos().max(1);
+        Decision {
+            allowed: true,
+            remaining: remaining as u32,
+            reset: wait,
+            retry_after: Duration::ZERO,
+        }
+    }
+}
+
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+impl Middleware for RateLimit {
+    fn handle<'a>(&'a self, ctx: &'a mut Context, next: Next<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let key = self.key.extract(ctx).unwrap_or_default();
+            let decision = self.check(&key, Instant::now());
+
+            if decision.allowed {
+                next.run(ctx).await;
+            } else {
+                let response = ctx.response_mut();
+                response.status_code(StatusCode::TooManyRequests);
+                response.write_body(b"Too Many Requests");
+                response.set_header("Retry-After", &ceil_secs(decision.retry_after).to_string());
+            }
+
+            let response = ctx.response_mut();
+            response.set_header("RateLimit-Limit", &self.limit.to_string());
+            response.set_header("RateLimit-Remaining", &decision.remaining.to_string());
+            response.set_header("RateLimit-Reset", &ceil_secs(decision.reset).to_string());
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Request, Response};
+    use bytes::Bytes;
+
+    #[test]
+    fn allows_burst_then_limits() {
+        let limiter = RateLimit::new(3, Duration::from_secs(3));
+        let now = Instant::now();
+
+        let remaining: Vec<u32> = (0..3).map(|_| limiter.check("a", now).remaining).collect();
+        assert_eq!(remaining, vec![2, 1, 0]);
+
+        let decision = limiter.check("a", now);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, Duration::from_secs(1));
+
+        // other keys are counted separately
+        assert!(limiter.check("b", now).allowed);
+    }
+
+    #[test]
+    fn refills_over_time() {
+        let limiter = RateLimit::new(2, Duration::from_secs(2));
+        let now = Instant::now();
+
+        assert!(limiter.check("a", now).allowed);
+        assert!(limiter.check("a", now).allowed);
+        assert!(!limiter.check("a", now).allowed);
+        assert!(!limiter.check("a", now + Duration::from_millis(500)).allowed);
+
+        let decision = limiter.check("a", now + Duration::from_secs(1));
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 0);
+
+        let decision = limiter.check("a", now + Duration::from_secs(10));
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 1);
+    }
+
+    #[test]
+    fn prunes_idle_keys_once_the_map_doubles() {
+        let limiter = RateLimit::new(1, Duration::from_secs(1));
+        let now = Instant::now();
+
+        for key in 0..MIN_PRUNE {
+            limiter.check(&key.to_string(), now);
+        }
+        assert_eq!(limiter.state.lock().unwrap().tats.len(), MIN_PRUNE);
+
+        // the earlier keys are still limited, so nothing is dropped and the next prune waits for twice as many
+        limiter.check("active", now);
+        let state = limiter.state.lock().unwrap();
+        assert_eq!(state.tats.len(), MIN_PRUNE + 1);
+        assert_eq!(state.prune_at, 2 * MIN_PRUNE);
+        drop(state);
+
+        for key in MIN_PRUNE..2 * MIN_PRUNE - 1 {
+            limiter.check(&key.to_string(), now + Duration::from_millis(500));
+        }
+        limiter.check("late", now + Duration::from_millis(1200));
+        let state = limiter.state.lock().unwrap();
+        assert_eq!(state.tats.len(), MIN_PRUNE);
+        assert!(state.tats.contains_key("late"));
+        assert!(!state.tats.contains_key("active"));
+    }
+
+    #[test]
+    fn new_keys_share_a_bucket_past_max_keys() {
+        let limiter = RateLimit::new(1, Duration::from_secs(10)).max_keys(2);
+        let now = Instant::now();
+
+        assert!(limiter.check("a", now).allowed);
+        assert!(limiter.check("b", now).allowed);
+        assert!(limiter.check("c", now).allowed);
+        assert!(!limiter.check("d", now).allowed);
+        assert!(!limiter.check("a", now).allowed);
+        assert_eq!(limiter.state.lock().unwrap().tats.len(), 3);
+
+        // once the tracked keys have refilled, pruning makes room again
+        assert!(limiter.check("e", now + Duration::from_secs(11)).allowed);
+        assert_eq!(limiter.state.lock().unwrap().tats.len(), 1);
+    }
+
+    async fn handler(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"ok");
+    }
+
+    async fn run(limiter: &RateLimit, raw: &'static [u8]) -> Response {
+        let mut request = Request::new(Bytes::from_static(raw)).expect("parsing request");
+        request.remote_addr = Some("10.0.0.1:5000".parse().unwrap());
+        let mut ctx = Context::new(request, Response::new());
+        limiter.handle(&mut ctx, Next::new(&[], &handler)).await;
+        ctx.into_response()
+    }
+
+    #[tokio::test]
+    async fn responds_429_with_headers() {
+        let limiter = RateLimit::new(1, Duration::from_secs(60));
+
+        let response = run(&limiter, b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert_eq!(response.status(), &StatusCode::Ok);
+        assert_eq!(response.get_header("RateLimit-Limit"), Some("1"));
+        assert_eq!(response.get_header("RateLimit-Remaining"), Some("0"));
+        assert_eq!(response.get_header("RateLimit-Reset"), Some("60"));
+
+        let response = run(&limiter, b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert_eq!(response.status(), &StatusCode::TooManyRequests);
+        assert_eq!(response.get_header("RateLimit-Remaining"), Some("0"));
+        assert!(response.get_header("Retry-After").is_some());
+    }
+
+    #[tokio::test]
+    async fn keyed_by_header() {
+        let limiter = RateLimit::new(1, Duration::from_secs(60)).key(RateLimitKey::Header("X-Api-Key".to_string()));
+
+        let response = run(&limiter, b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Api-Key: one\r\n\r\n").await;
+        assert_eq!(response.status(), &StatusCode::Ok);
+        let response = run(&limiter, b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Api-Key: two\r\n\r\n").await;
+        assert_eq!(response.status(), &StatusCode::Ok);
+        let response = run(&limiter, b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Api-Key: one\r\n\r\n").await;
+        assert_eq!(response.status(), &StatusCode::TooManyRequests);
+    }
+}
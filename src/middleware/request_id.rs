@@ -0,0 +1,228 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use crate::{response::validate_header, Context, HandlerFuture, Middleware, Next, Rejection};
+
+/// The ID of the current request, stored on the context by [`SetRequestId`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn new(id: impl Into<String>) -> Self {
+        RequestId(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// A random version 4 UUID. IDs are unique, but are not meant to be unguessable.
+    pub fn generate() -> Self {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let random = |salt: u64| {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+            hasher.write_u64(salt);
+            hasher.finish()
+        };
+
+        let high = (random(0) & 0xffff_ffff_ffff_0fff) | 0x4000;
+        let low = (random(1) & 0x3fff_ffff_ffff_ffff) | 0x8000_0000_0000_0000;
+        RequestId(format!(
+            "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            high >> 32,
+            (high >> 16) & 0xffff,
+            high & 0xffff,
+            low >> 48,
+            low & 0xffff_ffff_ffff
+        ))
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+pub type RequestIdGenerator = Arc<dyn Fn() -> String + Send + Sync>;
+
+/// Middleware that gives every request an ID, reusing the one in the `X-Request-Id` header when it
+/// is present and well formed. The ID is stored on the context as [`RequestId`], picked up by
+/// [`crate::AccessLog`], and echoed on the response, including error responses and, when added with
+/// [`crate::Server::middleware`], requests the server rejects. Add it before [`crate::AccessLog`] so
+/// rejected requests are logged with their ID.
+pub struct SetRequestId {
+    header: String,
+    generator: Option<RequestIdGenerator>,
+}
+
+impl Default for SetRequestId {
+    fn default() -> Self {
+        SetRequestId::new()
+    }
+}
+
+impl SetRequestId {
+    pub fn new() -> Self {
+        SetRequestId {
+            header: "X-Request-Id".to_string(),
+            generator: None,
+        }
+    }
+
+    /// # Panics
+    ///
+    /// If `header` is not a valid header name.
+    pub fn header(mut self, header: &str) -> Self {
+        if let Err(e) = validate_header(header, "") {
+            panic!("invalid request id header {:?}: {}", header, e);
+        }
+        self.header = header.to_string();
+        self
+    }
+
+    /// Generates IDs for requests without a usable one. Generated IDs that are empty, longer than 128
+    /// bytes or not printable ASCII are replaced with [`RequestId::generate`].
+    pub fn generator(mut self, generator: impl Fn() -> String + Send + Sync + 'static) -> Self {
+        self.generator = Some(Arc::new(generator));
+        self
+    }
+
+    fn generate(&self) -> RequestId {
+        match &self.generator {
+            Some(generator) => Some(generator())
+                .filter(|id| is_valid(id))
+                .map(RequestId)
+                .unwrap_or_else(RequestId::generate),
+            None => RequestId::generate(),
+        }
+    }
+}
+
+/// Incoming IDs are echoed back in a header and written to logs, so only short printable ones are reused.
+fn is_valid(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 128 && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+impl Middleware for SetRequestId {
+    fn handle<'a>(&'a self, ctx: &'a mut Context, next: Next<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let id = match ctx.request().headers.get_str(&self.header) {
+                Ok(id) if is_valid(&id) => RequestId(id),
+                _ => self.generate(),
+            };
+            ctx.insert(id.clone());
+
+            next.run(ctx).await;
+
+            // The header name is checked when it is configured and every ID is printable ASCII.
+            let _ = ctx.response_mut().try_set_header(&self.header, id.as_str());
+        })
+    }
+
+    /// Rejected requests could not be parsed, so they always get a generated ID.
+    fn rejected(&self, rejection: &mut Rejection) {
+        let id = self.generate();
+        let _ = rejection.response.try_set_header(&self.header, id.as_str());
+        rejection.extensions.insert(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Request, Response};
+    use bytes::Bytes;
+    use httpstatus::StatusCode;
+
+    async fn handler(ctx: &mut Context) {
+        let id = ctx.get::<RequestId>().map(RequestId::to_string).unwrap_or_default();
+        ctx.response_mut().write_body(id.as_bytes());
+    }
+
+    async fn fail(ctx: &mut Context) {
+        ctx.response_mut().status_code(StatusCode::InternalServerError);
+    }
+
+    async fn run(layer: &SetRequestId, raw: &'static [u8], endpoint: &dyn crate::Handler) -> Response {
+        let request = Request::new(Bytes::from_static(raw)).expect("parsing request");
+        let mut ctx = Context::new(request, Response::new());
+        layer.handle(&mut ctx, Next::new(&[], endpoint)).await;
+        ctx.into_response()
+    }
+
+    #[test]
+    fn generate_uuid_v4() {
+        let id = RequestId::generate().to_string();
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "4");
+        assert!(matches!(&id[19..20], "8" | "9" | "a" | "b"));
+        assert_ne!(RequestId::generate(), RequestId::generate());
+    }
+
+    #[tokio::test]
+    async fn reuse_incoming_id() {
+        let response = run(
+            &SetRequestId::new(),
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: abc-123\r\n\r\n",
+            &handler,
+        )
+        .await;
+        assert_eq!(response.body(), b"abc-123");
+        assert_eq!(response.get_header("X-Request-Id"), Some("abc-123"));
+    }
+
+    #[tokio::test]
+    async fn generate_when_missing_or_invalid() {
+        let layer = SetRequestId::new()
+            .header("X-Correlation-Id")
+            .generator(|| "generated".to_string());
+
+        let response = run(&layer, b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n", &handler).await;
+        assert_eq!(response.body(), b"generated");
+        assert_eq!(response.get_header("X-Correlation-Id"), Some("generated"));
+
+        let response = run(
+            &layer,
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Correlation-Id: has space\r\n\r\n",
+            &handler,
+        )
+        .await;
+        assert_eq!(response.body(), b"generated");
+    }
+
+    #[tokio::test]
+    async fn invalid_generated_id_is_replaced() {
+        let layer = SetRequestId::new().generator(|| "id\r\nSet-Cookie: x=1".to_string());
+        let response = run(&layer, b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n", &handler).await;
+        let id = response.get_header("X-Request-Id").unwrap();
+        assert_eq!(id.len(), 36);
+        assert_eq!(response.body(), id.as_bytes());
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid request id header")]
+    fn invalid_header_name_panics_when_configured() {
+        let _ = SetRequestId::new().header("X-Request Id");
+    }
+
+    #[tokio::test]
+    async fn echo_on_error_responses() {
+        let response = run(
+            &SetRequestId::new(),
+            b"GET / HTTP/1.1\r\nHost: localhost\r\nX-Request-Id: abc\r\n\r\n",
+            &fail,
+        )
+        .await;
+        assert_eq!(response.status(), &StatusCode::InternalServerError);
+        assert_eq!(response.get_header("X-Request-Id"), Some("abc"));
+    }
+}
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use httpstatus::StatusCode;
+
+use crate::{Context, HandlerFuture, Middleware, Next, RequestId, Response};
+
+/// Sets the handler timeout for every route below the server or router it is attached to.
+/// [`crate::Route::timeout`] overrides the duration for a single route.
+///
+/// The timeout covers route middleware and the handler. When it expires the handler future is
+/// dropped, the response is replaced with `status` (503 by default) and `body`, and the event is
+/// logged to stderr.
+#[derive(Debug, Clone)]
+pub struct Timeout {
+    duration: Duration,
+    status: StatusCode,
+    body: Bytes,
+}
+
+impl Timeout {
+    pub fn new(duration: Duration) -> Self {
+        Timeout {
+            duration,
+            status: StatusCode::ServiceUnavailable,
+            body: Bytes::from_static(b"Service Unavailable"),
+        }
+    }
+
+    /// Status sent on timeout, typically 503 or 504.
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Resolves the timeout for a matched route: the route's own duration if set, otherwise the
+    /// closest `Timeout` layer above it.
+    pub(crate) fn for_route(ctx: &Context, route_timeout: Option<Duration>) -> Option<Timeout> {
+        let inherited = ctx.get::<Timeout>().cloned();
+        match (route_timeout, inherited) {
+            (Some(duration), Some(inherited)) => Some(Timeout { duration, ..inherited }),
+            (Some(duration), None) => Some(Timeout::new(duration)),
+            (None, inherited) => inherited,
+        }
+    }
+
+    pub(crate) async fn run<'a>(&self, ctx: &'a mut Context, next: Next<'a>) {
+        if tokio::time::timeout(self.duration, next.run(ctx)).await.is_ok() {
+            return;
+        }
+
+        let request = ctx.request();
+        let id = ctx
+            .get::<RequestId>()
+            .map(|id| format!(" [{}]", id))
+            .unwrap_or_default();
+        eprintln!(
+            "Handler timed out after {:?}: {} {}{}",
+            self.duration, request.method, request.path, id
+        );
+
+        let response = ctx.response_mut();
+        *response = Response::new();
+        response.status_code(self.status.clone());
+        response.write_body(&self.body);
+    }
+}
+
+impl Middleware for Timeout {
+    fn handle<'a>(&'a self, ctx: &'a mut Context, next: Next<'a>) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let previous = ctx.insert(self.clone());
+            next.run(ctx).await;
+            match previous {
+                Some(previous) => ctx.insert(previous),
+                None => ctx.remove::<Timeout>(),
+            };
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Method, Request, Router};
+
+    async fn fast(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"fast");
+    }
+
+    async fn slow(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"partial");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        ctx.response_mut().write_body(b"slow");
+    }
+
+    async fn route(router: &Router, path: &str) -> Response {
+        let request = Request::new(Bytes::from(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path)))
+            .expect("parsing request");
+        let mut ctx = Context::new(request, Response::new());
+        router.route(&mut ctx, &Method::GET, path).await;
+        ctx.into_response()
+    }
+
+    #[tokio::test]
+    async fn cancel_slow_handler() {
+        let mut router = Router::new();
+        router.middleware(
+            Timeout::new(Duration::from_millis(10))
+                .status(StatusCode::GatewayTimeout)
+                .body("too slow"),
+        );
+        router.get("/fast", fast);
+        router.get("/slow", slow);
+
+        let response = route(&router, "/fast").await;
+        assert_eq!(response.body(), b"fast");
+
+        let response = route(&router, "/slow").await;
+        assert_eq!(response.status(), &StatusCode::GatewayTimeout);
+        assert_eq!(response.body(), b"too slow");
+    }
+
+    #[tokio::test]
+    async fn route_overrides_default() {
+        let mut router = Router::new();
+        router.middleware(Timeout::new(Duration::from_millis(10)));
+        router.get("/slow", slow).timeout(Duration::from_secs(5));
+        router.get("/strict", slow);
+
+        let response = route(&router, "/slow").await;
+        assert_eq!(response.body(), b"partialslow");
+
+        let response = route(&router, "/strict").await;
+        assert_eq!(response.status(), &StatusCode::ServiceUnavailable);
+    }
+
+    #[tokio::test]
+    async fn route_timeout_without_layer() {
+        let mut router = Router::new();
+        router.get("/slow", slow).timeout(Duration::from_millis(10));
+        router.get("/unlimited", slow);
+
+        let response = route(&router, "/slow").await;
+        assert_eq!(response.status(), &StatusCode::ServiceUnavailable);
+        assert_eq!(response.body(), b"Service Unavailable");
+
+        let response = route(&router, "/unlimited").await;
+        assert_eq!(response.body(), b"partialslow");
+    }
+}
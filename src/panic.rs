@@ -0,0 +1,130 @@
+use std::{
+    any::Any,
+    cell::Cell,
+    fmt,
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::Pin,
+    sync::Once,
+    task::{Context as TaskContext, Poll},
+};
+
+use crate::Method;
+
+/// Details of a handler panic, or of an error returned by a tower service, passed to the hook set with
+/// [`crate::Server::on_panic`].
+#[derive(Debug, Clone)]
+pub struct HandlerPanic {
+    pub message: String,
+    /// `file:line:column` of the panic, when [`crate::Server::capture_panic_locations`] is enabled.
+    pub location: Option<String>,
+    pub method: Method,
+    pub path: String,
+}
+
+impl fmt::Display for HandlerPanic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Handler panicked")?;
+        if let Some(location) = &self.location {
+            write!(f, " at {}", location)?;
+        }
+        write!(f, " while serving {} {}: {}", self.method, self.path, self.message)
+    }
+}
+
+thread_local! {
+    static CATCHING: Cell<usize> = const { Cell::new(0) };
+    static LOCATION: Cell<Option<String>> = const { Cell::new(None) };
+}
+
+/// Wraps the process panic hook, once, so panics caught by [`CatchUnwind`] record their location instead of
+/// being printed, leaving every other panic to the previous hook. The hook is process-wide, so this only
+/// happens when the application opts in with [`crate::Server::capture_panic_locations`].
+pub(crate) fn capture_locations() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if CATCHING.with(Cell::get) > 0 {
+                let location = info
+                    .location()
+                    .map(|location| format!("{}:{}:{}", location.file(), location.line(), location.column()));
+                LOCATION.with(|cell| cell.set(location));
+            } else {
+                previous(info);
+            }
+        }));
+    });
+}
+
+pub(crate) struct Caught {
+    pub message: String,
+    pub location: Option<String>,
+}
+
+fn message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+/// Polls the inner future inside `catch_unwind`, resolving to `Err` if it panics.
+pub(crate) struct CatchUnwind<F> {
+    inner: F,
+}
+
+impl<F> CatchUnwind<F> {
+    pub(crate) fn new(inner: F) -> Self {
+        CatchUnwind { inner }
+    }
+}
+
+impl<F: Future + Unpin> Future for CatchUnwind<F> {
+    type Output = Result<F::Output, Caught>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Self::Output> {
+        let inner = &mut self.inner;
+        CATCHING.with(|depth| depth.set(depth.get() + 1));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| Pin::new(inner).poll(cx)));
+        CATCHING.with(|depth| depth.set(depth.get() - 1));
+
+        match result {
+            Ok(poll) => poll.map(Ok),
+            Err(payload) => Poll::Ready(Err(Caught {
+                message: message(payload.as_ref()),
+                location: LOCATION.with(Cell::take),
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn catch_panic_with_location() {
+        capture_locations();
+        let result = CatchUnwind::new(Box::pin(async {
+            tokio::task::yield_now().await;
+            panic!("boom {}", 42);
+        }))
+        .await;
+
+        let caught = result.expect_err("panic is caught");
+        assert_eq!(caught.message, "boom 42");
+        assert!(caught
+            .location
+            .is_some_and(|location| location.starts_with("src/panic.rs:")));
+    }
+
+    #[tokio::test]
+    async fn pass_through_output() {
+        let result = CatchUnwind::new(Box::pin(async { 7 })).await;
+        assert!(matches!(result, Ok(7)));
+    }
+}
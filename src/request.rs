@@ -1,23 +1,136 @@
 use bytes::{Buf, Bytes};
 use core::fmt;
-use std::collections::{btree_map, BTreeMap};
+use std::{net::SocketAddr, ops::Deref};
 
-use crate::{tokens, HeaderError, RequestError};
+use crate::{tokens, Header, HeaderError, Host, ParseError, RequestError, RequestTarget};
 
+/// UTF-8 text that shares the buffer it was parsed from, so cloning it is cheap. Derefs to `str`.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct ByteStr(Bytes);
+
+impl ByteStr {
+    pub const fn from_static(value: &'static str) -> Self {
+        ByteStr(Bytes::from_static(value.as_bytes()))
+    }
+
+    pub fn from_utf8(bytes: Bytes) -> Result<Self, std::str::Utf8Error> {
+        std::str::from_utf8(&bytes)?;
+        Ok(ByteStr(bytes))
+    }
+
+    pub fn as_str(&self) -> &str {
+        // SAFETY: every constructor checks or guarantees that the bytes are UTF-8.
+        unsafe { std::str::from_utf8_unchecked(&self.0) }
+    }
+
+    pub fn as_bytes(&self) -> &Bytes {
+        &self.0
+    }
+
+    /// Shares the buffer for `subset`, which must be a slice of `self.as_str()`.
+    ///
+    /// # Panics
+    ///
+    /// If `subset` does not point into this string.
+    pub fn slice_ref(&self, subset: &str) -> ByteStr {
+        ByteStr(self.0.slice_ref(subset.as_bytes()))
+    }
+}
+
+impl Deref for ByteStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for ByteStr {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<String> for ByteStr {
+    fn from(value: String) -> Self {
+        ByteStr(Bytes::from(value))
+    }
+}
+
+impl From<&str> for ByteStr {
+    fn from(value: &str) -> Self {
+        ByteStr(Bytes::copy_from_slice(value.as_bytes()))
+    }
+}
+
+impl PartialEq<str> for ByteStr {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for ByteStr {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for ByteStr {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl fmt::Debug for ByteStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for ByteStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Request header fields in the order they were received. Names are matched case-insensitively and a
+/// field can appear more than once. Parsed names and values are slices of the request buffer.
 #[derive(Debug, Default, Clone)]
 pub struct RequestHeaders {
-    headers: BTreeMap<String, Vec<u8>>,
+    headers: Vec<(ByteStr, Bytes)>,
 }
 
 impl RequestHeaders {
     pub fn new() -> Self {
+        RequestHeaders { headers: Vec::new() }
+    }
+
+    /// Sized for the lines left in `bytes`, up to `max_headers`, so parsing does not reallocate.
+    fn with_capacity(bytes: &Bytes, max_headers: usize) -> Self {
+        let header_end = bytes
+            .windows(2)
+            .position(|w| w == b"\n\n" || w == b"\n\r")
+            .unwrap_or(bytes.len());
+        let lines = bytes[..header_end].iter().filter(|b| **b == b'\n').count() + 1;
         RequestHeaders {
-            headers: BTreeMap::new(),
+            headers: Vec::with_capacity(lines.min(max_headers)),
         }
     }
 
-    pub fn iter(&mut self) -> btree_map::Iter<String, Vec<u8>> {
-        self.headers.iter()
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Bytes)> {
+        self.headers.iter().map(|(name, value)| (name.as_str(), value))
+    }
+
+    pub fn len(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty()
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.get(key).is_ok()
     }
 
     pub fn get_str(&self, key: &str) -> Result<String, HeaderError> {
@@ -25,111 +138,424 @@ impl RequestHeaders {
         String::from_utf8(header.to_vec()).map_err(|_| HeaderError::InvalidString)
     }
 
-    pub fn get(&self, key: &str) -> Result<&Vec<u8>, HeaderError> {
-        self.headers.get(key).ok_or(HeaderError::NotFound)
+    /// The first value of `key`.
+    pub fn get(&self, key: &str) -> Result<&Bytes, HeaderError> {
+        self.headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+            .ok_or(HeaderError::NotFound)
+    }
+
+    /// Every value of `key`, in the order received.
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a Bytes> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    /// Every value of `key` joined with `", "`, which is equivalent to the separate fields for any list-based
+    /// header (RFC 9110, section 5.3). Not meaningful for `Set-Cookie`. A single value is returned without
+    /// copying.
+    pub fn get_combined(&self, key: &str) -> Result<Bytes, HeaderError> {
+        let mut values = self.get_all(key);
+        let first = values.next().ok_or(HeaderError::NotFound)?;
+        let Some(second) = values.next() else {
+            return Ok(first.clone());
+        };
+
+        let mut combined = first.to_vec();
+        for value in std::iter::once(second).chain(values) {
+            combined.extend_from_slice(b", ");
+            combined.extend_from_slice(value);
+        }
+        Ok(combined.into())
+    }
+
+    /// Decodes a typed header from every value of `H::NAME`.
+    pub fn typed_get<H: Header>(&self) -> Result<H, HeaderError> {
+        let value = self.get_combined(H::NAME)?;
+        H::decode(std::str::from_utf8(&value).map_err(|_| HeaderError::InvalidString)?)
+    }
+
+    pub fn typed_set<H: Header>(&mut self, header: &H) {
+        self.insert(H::NAME, header.encode());
+    }
+
+    /// Sets `key` to a single value in place of the first existing one, dropping the rest. Returns the first
+    /// previous value.
+    pub fn insert(&mut self, key: &str, value: impl Into<Bytes>) -> Option<Bytes> {
+        let index = self.headers.iter().position(|(name, _)| name.eq_ignore_ascii_case(key));
+        let previous = self.remove(key);
+        let index = index.unwrap_or(self.headers.len());
+        self.headers.insert(index, (key.into(), value.into()));
+        previous
+    }
+
+    /// Adds a value for `key` after any existing ones.
+    pub fn append(&mut self, key: &str, value: impl Into<Bytes>) {
+        self.headers.push((key.into(), value.into()));
+    }
+
+    /// Removes every value of `key`. Returns the first one.
+    pub fn remove(&mut self, key: &str) -> Option<Bytes> {
+        let mut removed = None;
+        self.headers.retain_mut(|(name, value)| {
+            if !name.eq_ignore_ascii_case(key) {
+                return true;
+            }
+            if removed.is_none() {
+                removed = Some(std::mem::take(value));
+            }
+            false
+        });
+        removed
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+/// Request method. Names are case-sensitive; any other valid token is kept as an
+/// [`Method::Extension`], such as the WebDAV `PROPFIND` or `MKCOL`.
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Method {
     OPTIONS,
     GET,
     HEAD,
     POST,
     PUT,
+    PATCH,
     DELETE,
     TRACE,
     CONNECT,
     ANY,
+    Extension(String),
 }
 
-impl fmt::Display for Method {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let method_str = match self {
+impl Method {
+    pub fn as_str(&self) -> &str {
+        match self {
             Method::OPTIONS => "OPTIONS",
             Method::GET => "GET",
             Method::HEAD => "HEAD",
             Method::POST => "POST",
             Method::PUT => "PUT",
+            Method::PATCH => "PATCH",
             Method::DELETE => "DELETE",
             Method::TRACE => "TRACE",
             Method::CONNECT => "CONNECT",
             Method::ANY => "ANY",
-        };
-        write!(f, "{}", method_str)
+            Method::Extension(method) => method,
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
     }
 }
 
 impl TryFrom<&str> for Method {
     type Error = &'static str;
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        match value.to_ascii_uppercase().as_str() {
-            "OPTION" => Ok(Method::OPTIONS),
-            "GET" => Ok(Method::GET),
-            "HEAD" => Ok(Method::HEAD),
-            "POST" => Ok(Method::POST),
-            "PUT" => Ok(Method::PUT),
-            "DELETE" => Ok(Method::DELETE),
-            "TRACE" => Ok(Method::TRACE),
-            "CONNECT" => Ok(Method::CONNECT),
-            "ANY" => Ok(Method::ANY),
-            _ => Err("invalid method"),
+        const METHODS: [(&str, Method); 10] = [
+            ("OPTIONS", Method::OPTIONS),
+            ("GET", Method::GET),
+            ("HEAD", Method::HEAD),
+            ("POST", Method::POST),
+            ("PUT", Method::PUT),
+            ("PATCH", Method::PATCH),
+            ("DELETE", Method::DELETE),
+            ("TRACE", Method::TRACE),
+            ("CONNECT", Method::CONNECT),
+            ("ANY", Method::ANY),
+        ];
+        if let Some((_, method)) = METHODS.iter().find(|(name, _)| *name == value) {
+            return Ok(method.clone());
+        }
+        if value.is_empty() || !value.bytes().all(tokens::is_header_name_token) {
+            return Err("invalid method");
+        }
+        Ok(Method::Extension(value.to_string()))
+    }
+}
+
+/// HTTP version of a request or response. Only HTTP/1.x is spoken.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    #[default]
+    Http11,
+}
+
+impl Version {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
         }
     }
 }
 
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A parsed request. The target, header fields and body are slices of the buffer it was parsed from.
 #[derive(Debug, Clone)]
 pub struct Request {
     pub method: Method,
-    pub path: String,
-    pub version: u8,
+    pub target: RequestTarget,
+    /// Path of the target, without the query, used for routing.
+    pub path: ByteStr,
+    pub version: Version,
     pub headers: RequestHeaders,
-    pub body: Vec<u8>,
+    pub body: Bytes,
+    /// Peer address of the connection, set by the server.
+    pub remote_addr: Option<SocketAddr>,
+}
+
+/// How [`Request::parse`] treats malformed input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Follows RFC 9112: every malformed element is an error.
+    #[default]
+    Strict,
+    /// Falls back to `GET`, an empty path and HTTP/1.1 when those are invalid, and stops reading headers at
+    /// the first malformed one, leaving the rest to the body. The request line must still end after the
+    /// version: a byte that cannot appear in the request line at all, such as `<` in the target, stops it
+    /// early and fails with [`RequestError::NewLine`]. The body length is checked as strictly as in
+    /// [`ParseMode::Strict`], since guessing it would let requests be smuggled.
+    Lenient,
+}
+
+/// Size limits applied while parsing a request, so a client cannot make the server buffer or index an
+/// unbounded header section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// Longest request line, without its line ending. Longer ones fail with [`RequestError::URITooLong`].
+    pub request_line: usize,
+    /// Longest single header line, without its line ending. Longer ones fail with
+    /// [`RequestError::HeadersTooLarge`].
+    pub header_size: usize,
+    /// Largest header section, request line included. Larger ones fail with
+    /// [`RequestError::HeadersTooLarge`].
+    pub header_bytes: usize,
+    /// Most header fields. More fail with [`RequestError::TooManyHeaders`].
+    pub headers: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            request_line: 8 * 1024,
+            header_size: 8 * 1024,
+            header_bytes: 64 * 1024,
+            headers: 100,
+        }
+    }
 }
 
 impl Request {
-    pub fn new(buf: Bytes) -> Result<Self, RequestError> {
+    /// Parses a request in [`ParseMode::Strict`].
+    pub fn new(buf: Bytes) -> Result<Self, ParseError> {
+        Request::parse(buf, ParseMode::Strict)
+    }
+
+    /// Parses a request with the default [`Limits`].
+    pub fn parse(buf: Bytes, mode: ParseMode) -> Result<Self, ParseError> {
+        Request::parse_with_limits(buf, mode, &Limits::default())
+    }
+
+    pub fn parse_with_limits(buf: Bytes, mode: ParseMode, limits: &Limits) -> Result<Self, ParseError> {
+        Request::parse_prefix(buf, mode, limits).map(|(request, _)| request)
+    }
+
+    /// Like [`Request::parse_with_limits`], also returning how many bytes at the start of `buf` the request
+    /// took up, so the server can check that it framed the request the same way.
+    pub(crate) fn parse_prefix(buf: Bytes, mode: ParseMode, limits: &Limits) -> Result<(Self, usize), ParseError> {
+        let len = buf.len();
+        let offset = |bytes: &Bytes| len - bytes.len();
+        let lenient = mode == ParseMode::Lenient;
         let mut bytes = buf;
-        let method = Request::parse_token(&mut bytes)?
-            .as_str()
-            .try_into()
-            .map_err(|_| RequestError::Method)
-            .unwrap_or(Method::GET);
-        let path = Request::parse_uri(&mut bytes).unwrap_or_default();
-        let version = Request::parse_version(&mut bytes).unwrap_or(1);
-        Request::parse_new_line(&mut bytes)?;
-        let headers = Request::parse_headers(&mut bytes)?;
-        let body = Request::parse_body(&mut bytes, &headers)?;
-
-        Ok(Request {
+
+        let start = offset(&bytes);
+        let token = Request::parse_token(&mut bytes).map_err(|e| e.at(start))?;
+        let method = match Method::try_from(token.as_str()) {
+            Ok(method) => method,
+            Err(_) if lenient => Method::GET,
+            Err(_) => return Err(RequestError::Method.at(start)),
+        };
+
+        let start = offset(&bytes);
+        let target = match Request::parse_uri(&mut bytes).and_then(|uri| RequestTarget::parse(uri, &method)) {
+            Ok(target) => target,
+            Err(_) if lenient => RequestTarget::default(),
+            Err(e) => return Err(e.at(start)),
+        };
+
+        let start = offset(&bytes);
+        let version = match Request::parse_version(&mut bytes) {
+            Ok(version) => version,
+            Err(_) if lenient => Version::Http11,
+            Err(e) => return Err(e.at(start)),
+        };
+
+        let start = offset(&bytes);
+        if start > limits.request_line {
+            return Err(RequestError::URITooLong.at(0));
+        }
+        Request::parse_new_line(&mut bytes).map_err(|e| e.at(start))?;
+
+        let start = offset(&bytes);
+        let mut headers = if lenient {
+            Request::parse_headers_lenient(&mut bytes, len, limits)?
+        } else {
+            let headers = Request::parse_headers_strict(&mut bytes, len, limits)?;
+            Request::check_host(&headers, version).map_err(|e| e.at(start))?;
+            headers
+        };
+        // RFC 9112 section 3.2.2: the authority of the target takes precedence over the Host header.
+        if let Some(authority) = &target.uri.authority {
+            headers.insert("Host", authority.as_bytes().clone());
+        }
+
+        let start = offset(&bytes);
+        let body = Request::parse_body(&mut bytes, &headers, mode).map_err(|e| e.at(start))?;
+
+        let request = Request {
             method,
-            path,
+            path: target.path().clone(),
+            target,
             version,
             headers,
             body,
-        })
+            remote_addr: None,
+        };
+        Ok((request, offset(&bytes)))
+    }
+
+    /// The `Host` of the request, with the name lower-cased and any trailing dot removed. For absolute-form
+    /// and authority-form targets this is the authority of the target.
+    pub fn host(&self) -> Option<Host> {
+        let mut host = self.headers.typed_get::<Host>().ok()?;
+        host.host.make_ascii_lowercase();
+        if host.host.ends_with('.') {
+            host.host.pop();
+        }
+        Some(host)
+    }
+
+    /// RFC 9112 section 3.2: an HTTP/1.1 request carries exactly one valid `Host`, an HTTP/1.0 one at most one.
+    fn check_host(headers: &RequestHeaders, version: Version) -> Result<(), RequestError> {
+        let mut hosts = headers.get_all("Host");
+        match (hosts.next(), hosts.next()) {
+            (None, None) if version == Version::Http10 => Ok(()),
+            (Some(host), None) => std::str::from_utf8(host)
+                .ok()
+                .and_then(|host| Host::decode(host).ok())
+                .map(drop)
+                .ok_or(RequestError::Host),
+            _ => Err(RequestError::Host),
+        }
     }
 
+    /// Reads header fields until the first malformed line, with the default [`Limits`].
     pub fn parse_headers(bytes: &mut Bytes) -> Result<RequestHeaders, RequestError> {
-        let mut headers = RequestHeaders::new();
-        let mut parse_header = || -> Result<(), RequestError> {
-            let header_name = Request::parse_header_name(bytes)?;
-            Request::parse_space(bytes)?;
-            let header_value = Request::parse_header_value(bytes)?;
-            headers.headers.insert(header_name, header_value);
-            Ok(())
-        };
+        let len = bytes.len();
+        Request::parse_headers_lenient(bytes, len, &Limits::default()).map_err(|e| e.kind)
+    }
 
+    fn parse_headers_lenient(bytes: &mut Bytes, len: usize, limits: &Limits) -> Result<RequestHeaders, ParseError> {
+        let mut headers = RequestHeaders::with_capacity(bytes, limits.headers);
         loop {
-            if parse_header().is_err() {
-                break;
+            let start = len - bytes.len();
+            let mut parse_header = || -> Result<(ByteStr, Bytes, usize), RequestError> {
+                let header_name = Request::parse_header_name(bytes)?;
+                Request::parse_space(bytes)?;
+                let (header_value, line_ending) = Request::parse_header_value(bytes)?;
+                Ok((header_name, header_value, line_ending))
+            };
+            let Ok((name, value, line_ending)) = parse_header() else {
+                return Ok(headers);
+            };
+            Request::check_header_limits(&headers, start, len - bytes.len(), line_ending, limits)
+                .map_err(|e| e.at(start))?;
+            headers.headers.push((name, value));
+        }
+    }
+
+    /// Reads header fields up to and including the empty line that ends them. `len` is the length of the
+    /// whole request, to report offsets.
+    fn parse_headers_strict(bytes: &mut Bytes, len: usize, limits: &Limits) -> Result<RequestHeaders, ParseError> {
+        let mut headers = RequestHeaders::with_capacity(bytes, limits.headers);
+        loop {
+            let start = len - bytes.len();
+            match bytes.first() {
+                None => return Err(RequestError::NewLine.at(start)),
+                Some(b'\r' | b'\n') => {
+                    Request::parse_new_line(bytes).map_err(|e| e.at(start))?;
+                    return Ok(headers);
+                }
+                Some(_) => {}
             }
+
+            let name = match Request::parse_header_name(bytes) {
+                Ok(name) if !name.is_empty() => name,
+                _ => return Err(RequestError::HeaderName.at(start)),
+            };
+            let value_start = len - bytes.len();
+            let (value, line_ending) =
+                Request::parse_field_value(bytes).map_err(|_| RequestError::HeaderValue.at(value_start))?;
+            Request::check_header_limits(&headers, start, len - bytes.len(), line_ending, limits)
+                .map_err(|e| e.at(start))?;
+            headers.headers.push((name, value));
+        }
+    }
+
+    /// Checks a header line that started at `start` and ends at `end`, before it is added to `headers`.
+    /// `end` includes the line ending, which is `line_ending` bytes long.
+    fn check_header_limits(
+        headers: &RequestHeaders,
+        start: usize,
+        end: usize,
+        line_ending: usize,
+        limits: &Limits,
+    ) -> Result<(), RequestError> {
+        if headers.len() >= limits.headers {
+            return Err(RequestError::TooManyHeaders);
+        }
+        if end - start > limits.header_size + line_ending || end > limits.header_bytes {
+            return Err(RequestError::HeadersTooLarge);
+        }
+        Ok(())
+    }
+
+    /// Reads a field value surrounded by optional whitespace, and the line ending after it. Returns the value
+    /// and the length of the line ending.
+    fn parse_field_value(bytes: &mut Bytes) -> Result<(Bytes, usize), RequestError> {
+        let leading = bytes.iter().take_while(|b| matches!(b, b' ' | b'\t')).count();
+        bytes.advance(leading);
+
+        let end = bytes
+            .iter()
+            .position(|b| !tokens::is_header_value_token(*b))
+            .ok_or(RequestError::NewLine)?;
+        if !matches!(bytes[end], b'\r' | b'\n') {
+            return Err(RequestError::HeaderValue);
         }
 
-        Ok(headers)
+        let trimmed = bytes[..end].trim_ascii_end().len();
+        let mut value = bytes.split_to(end);
+        value.truncate(trimmed);
+        let line_ending = Request::parse_new_line(bytes)?;
+        Ok((value, line_ending))
     }
 
-    fn parse_new_line(bytes: &mut Bytes) -> Result<(), RequestError> {
+    /// Reads a CRLF or a bare LF, returning how many bytes it took up.
+    fn parse_new_line(bytes: &mut Bytes) -> Result<usize, RequestError> {
         if !bytes.has_remaining() {
             return Err(RequestError::NewLine);
         }
@@ -137,33 +563,32 @@ impl Request {
         match bytes.get_u8() {
             b'\r' => {
                 if bytes.has_remaining() && bytes.get_u8() == b'\n' {
-                    Ok(())
+                    Ok(2)
                 } else {
                     Err(RequestError::NewLine)
                 }
             }
-            b'\n' => Ok(()),
+            b'\n' => Ok(1),
             _ => Err(RequestError::NewLine),
         }
     }
 
-    fn parse_version(bytes: &mut Bytes) -> Result<u8, RequestError> {
-        let res = match &bytes.slice(0..8)[..] {
-            b"HTTP/1.1" => Ok(1),
-            b"HTTP/2" => Ok(2),
-            b"HTTP/3" => Ok(3),
+    fn parse_version(bytes: &mut Bytes) -> Result<Version, RequestError> {
+        let version = match bytes.get(..8) {
+            Some(b"HTTP/1.0") => Version::Http10,
+            Some(b"HTTP/1.1") => Version::Http11,
             _ => return Err(RequestError::Version),
         };
         bytes.advance(8);
-        res
+        Ok(version)
     }
 
-    fn parse_uri(bytes: &mut Bytes) -> Result<String, RequestError> {
+    fn parse_uri(bytes: &mut Bytes) -> Result<ByteStr, RequestError> {
         for (i, b) in bytes.iter().enumerate() {
             if b == &b' ' {
-                let token = &bytes.slice(0..i)[..];
-                bytes.advance(i + 1);
-                return Ok(std::str::from_utf8(token).map_err(|_| RequestError::URI)?.to_string());
+                let token = bytes.split_to(i);
+                bytes.advance(1);
+                return ByteStr::from_utf8(token).map_err(|_| RequestError::URI);
             } else if !tokens::is_uri_token(*b) {
                 break;
             }
@@ -171,14 +596,13 @@ impl Request {
         Err(RequestError::URI)
     }
 
-    fn parse_token(bytes: &mut Bytes) -> Result<String, RequestError> {
+    fn parse_token(bytes: &mut Bytes) -> Result<ByteStr, RequestError> {
         for (i, b) in bytes.iter().enumerate() {
             if b == &b' ' {
-                let token = &bytes.slice(0..i)[..];
-                bytes.advance(i + 1);
-                return Ok(std::str::from_utf8(token).map_err(|_| RequestError::Token)?.to_string());
+                let token = bytes.split_to(i);
+                bytes.advance(1);
+                return ByteStr::from_utf8(token).map_err(|_| RequestError::Token);
             } else if !tokens::is_token(*b) {
-                println!("{}", b);
                 break;
             }
         }
@@ -196,12 +620,12 @@ impl Request {
         }
     }
 
-    fn parse_header_name(bytes: &mut Bytes) -> Result<String, RequestError> {
+    fn parse_header_name(bytes: &mut Bytes) -> Result<ByteStr, RequestError> {
         for (i, b) in bytes.iter().enumerate() {
             if b == &b':' {
-                let token = &bytes.slice(0..i)[..];
-                bytes.advance(i + 1);
-                return Ok(std::str::from_utf8(token).map_err(|_| RequestError::Token)?.to_string());
+                let token = bytes.split_to(i);
+                bytes.advance(1);
+                return ByteStr::from_utf8(token).map_err(|_| RequestError::Token);
             } else if !tokens::is_header_name_token(*b) {
                 break;
             }
@@ -209,13 +633,12 @@ impl Request {
         Err(RequestError::Token)
     }
 
-    fn parse_header_value(bytes: &mut Bytes) -> Result<Vec<u8>, RequestError> {
+    fn parse_header_value(bytes: &mut Bytes) -> Result<(Bytes, usize), RequestError> {
         for (i, b) in bytes.iter().enumerate() {
             if b == &b'\r' || b == &b'\n' {
-                let token = &bytes.slice(0..i)[..];
-                bytes.advance(i);
-                Request::parse_new_line(bytes)?;
-                return Ok(token.to_vec());
+                let token = bytes.split_to(i);
+                let line_ending = Request::parse_new_line(bytes)?;
+                return Ok((token, line_ending));
             } else if !tokens::is_header_value_token(*b) {
                 break;
             }
@@ -223,46 +646,160 @@ impl Request {
         Err(RequestError::Token)
     }
 
-    fn parse_body(bytes: &mut Bytes, headers: &RequestHeaders) -> Result<Vec<u8>, RequestError> {
-        if let Some(content_length) = headers.headers.get("Content-Length") {
-            let content_length = std::str::from_utf8(content_length).map_err(|_| RequestError::HeaderContentLength)?;
-            let content_length: usize = content_length.parse().map_err(|_| RequestError::HeaderContentLength)?;
+    fn parse_body(bytes: &mut Bytes, headers: &RequestHeaders, mode: ParseMode) -> Result<Bytes, RequestError> {
+        let content_length = body_length(headers.iter().map(|(name, value)| (name.as_bytes(), value.as_ref())))?;
 
+        // The lenient header parser leaves the empty line after the headers in place.
+        if mode == ParseMode::Lenient && headers.contains("Content-Length") {
             Request::parse_new_line(bytes)?;
-            if bytes.remaining() < content_length {
-                return Err(RequestError::IncompleteBody);
-            }
-
-            let body = bytes[..content_length].to_vec();
-            bytes.advance(content_length);
-            return Ok(body);
         }
+        if bytes.remaining() < content_length {
+            return Err(RequestError::IncompleteBody);
+        }
+        Ok(bytes.split_to(content_length))
+    }
+}
 
-        Ok(vec![])
+/// Body length declared by the header `fields`, following RFC 9112 section 6.3. Shared by the parser and
+/// the server's framing so both always agree on where a request ends.
+///
+/// `Transfer-Encoding` is not supported and fails with [`RequestError::TransferEncoding`]. Every
+/// `Content-Length` must be 1*DIGIT and all of them must match, otherwise the length is ambiguous and
+/// fails with [`RequestError::HeaderContentLength`].
+pub(crate) fn body_length<'a>(fields: impl IntoIterator<Item = (&'a [u8], &'a [u8])>) -> Result<usize, RequestError> {
+    let mut content_length = None;
+    for (name, value) in fields {
+        if name.eq_ignore_ascii_case(b"transfer-encoding") {
+            return Err(RequestError::TransferEncoding);
+        }
+        if !name.eq_ignore_ascii_case(b"content-length") {
+            continue;
+        }
+        let value = value.trim_ascii();
+        if value.is_empty() || !value.iter().all(u8::is_ascii_digit) {
+            return Err(RequestError::HeaderContentLength);
+        }
+        // Digits only, so this is valid UTF-8 and only fails on overflow.
+        let length = std::str::from_utf8(value)
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+            .ok_or(RequestError::HeaderContentLength)?;
+        if content_length.is_some_and(|previous| previous != length) {
+            return Err(RequestError::HeaderContentLength);
+        }
+        content_length = Some(length);
     }
+    Ok(content_length.unwrap_or(0))
 }
 
 #[cfg(test)]
 mod tests {
     use bytes::Bytes;
+    use rstest::rstest;
 
     use super::*;
+    use crate::{AcceptEncoding, ContentLength, Host, Range};
 
     #[test]
     fn parse_basic_requests() {
-        let mut request = Request::new(Bytes::from_static(b"GET /test HTTP/1.1\r\n\r\n")).expect("parsing request");
+        let request = Request::new(Bytes::from_static(b"GET /test HTTP/1.1\r\nHost: localhost\r\n\r\n"))
+            .expect("parsing request");
 
-        assert_eq!(request.version, 1);
-        assert_eq!(request.method, Some(Method::GET));
+        assert_eq!(request.version, Version::Http11);
+        assert_eq!(request.method, Method::GET);
         assert_eq!(request.path, String::from("/test"));
     }
 
+    #[rstest]
+    #[case("PATCH", Ok(Method::PATCH))]
+    #[case("OPTIONS", Ok(Method::OPTIONS))]
+    #[case("PROPFIND", Ok(Method::Extension("PROPFIND".to_string())))]
+    #[case("get", Ok(Method::Extension("get".to_string())))]
+    #[case("OPTION", Ok(Method::Extension("OPTION".to_string())))]
+    #[case("GE T", Err("invalid method"))]
+    #[case("", Err("invalid method"))]
+    fn method_tokens(#[case] token: &str, #[case] expected: Result<Method, &str>) {
+        assert_eq!(Method::try_from(token), expected);
+    }
+
+    #[test]
+    fn absolute_form_authority_replaces_host() {
+        let raw = b"GET http://example.com/users?id=7 HTTP/1.1\r\nHost: other.test\r\n\r\n";
+        let request = Request::new(Bytes::from_static(raw)).expect("parsing request");
+
+        assert_eq!(request.path, "/users");
+        assert_eq!(request.target.query(), Some("id=7"));
+        assert_eq!(request.target.to_string(), "http://example.com/users?id=7");
+        assert_eq!(request.headers.get_str("Host").unwrap(), "example.com");
+        assert_eq!(request.headers.get_all("Host").count(), 1);
+    }
+
+    #[rstest]
+    #[case::request_line(
+        b"GET /aaaaaaaaaaaaaaaaaaaa HTTP/1.1\r\nHost: a\r\n\r\n",
+        RequestError::URITooLong,
+        0
+    )]
+    #[case::header_size(
+        b"GET / HTTP/1.1\r\nHost: a\r\nX-Large: aaaaaaaaaa\r\n\r\n",
+        RequestError::HeadersTooLarge,
+        25
+    )]
+    #[case::header_size_bare_lf(b"GET / HTTP/1.1\nHost: a\nX-Large: aaaa\n\n", RequestError::HeadersTooLarge, 23)]
+    #[case::header_count(
+        b"GET / HTTP/1.1\r\nHost: a\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n",
+        RequestError::TooManyHeaders,
+        37
+    )]
+    #[case::header_bytes(
+        b"GET / HTTP/1.1\r\nHost: a\r\nX-One: aaaa\r\nX-Two: bbbb\r\n\r\n",
+        RequestError::HeadersTooLarge,
+        38
+    )]
+    fn limits_are_enforced_in_both_modes(
+        #[case] raw: &'static [u8],
+        #[case] kind: RequestError,
+        #[case] offset: usize,
+    ) {
+        let limits = Limits {
+            request_line: 20,
+            header_size: 12,
+            header_bytes: 50,
+            headers: 3,
+        };
+        for mode in [ParseMode::Strict, ParseMode::Lenient] {
+            let error =
+                Request::parse_with_limits(Bytes::from_static(raw), mode, &limits).expect_err("parsing request");
+            assert_eq!(error.kind, kind);
+            assert_eq!(error.offset, offset);
+        }
+    }
+
+    #[test]
+    fn normalized_host() {
+        let raw = b"GET / HTTP/1.1\r\nHost: API.Example.com.:8080\r\n\r\n";
+        let request = Request::new(Bytes::from_static(raw)).expect("parsing request");
+        let host = request.host().unwrap();
+
+        assert_eq!(host.host, "api.example.com");
+        assert_eq!(host.port, Some(8080));
+    }
+
+    #[test]
+    fn parse_http_1_0_without_host() {
+        let request = Request::new(Bytes::from_static(b"GET /test HTTP/1.0\r\n\r\n")).expect("parsing request");
+
+        assert_eq!(request.version, Version::Http10);
+        assert_eq!(request.path, "/test");
+    }
+
     #[test]
     fn accept_only_newline() {
-        let request = Request::new(Bytes::from_static(b"GET /test HTTP/1.1\r\n\r\n")).expect("parsing request");
+        let request = Request::new(Bytes::from_static(b"GET /test HTTP/1.1\r\nHost: localhost\r\n\r\n"))
+            .expect("parsing request");
 
-        assert_eq!(request.version, 1);
-        assert_eq!(request.method, Some(Method::GET));
+        assert_eq!(request.version, Version::Http11);
+        assert_eq!(request.method, Method::GET);
         assert_eq!(request.path, String::from("/test"));
     }
 
@@ -274,53 +811,162 @@ mod tests {
     #[test]
     fn parse_request_with_headers() {
         let request = Request::new(Bytes::from_static(
-            b"GET /test HTTP/1.1\r\nContent-Type: application/json\r\nAuthorization: Bearer token\r\n\r\n",
+            b"GET /test HTTP/1.1\r\nHost: localhost\r\nContent-Type: application/json\r\nAuthorization: Bearer token\r\n\r\n",
         ))
         .expect("parsing request");
 
-        assert_eq!(request.version, 1);
-        assert_eq!(request.method, Some(Method::GET));
+        assert_eq!(request.version, Version::Http11);
+        assert_eq!(request.method, Method::GET);
         assert_eq!(request.path, String::from("/test"));
-        assert_eq!(request.headers.iter().count(), 2);
+        assert_eq!(request.headers.iter().count(), 3);
         assert_eq!(
-            request.headers.iter().find(|(k, _)| k.as_str() == "Content-Type"),
-            Some((&String::from("Content-Type"), &b"application/json"[..].to_vec()))
+            request.headers.iter().find(|(k, _)| *k == "Content-Type"),
+            Some(("Content-Type", &Bytes::from_static(b"application/json")))
         );
         assert_eq!(
-            request.headers.iter().find(|(k, _)| k.as_str() == "Authorization"),
-            Some((&String::from("Authorization"), &b"Bearer token"[..].to_vec()))
+            request.headers.iter().find(|(k, _)| *k == "Authorization"),
+            Some(("Authorization", &Bytes::from_static(b"Bearer token")))
         );
     }
 
+    #[test]
+    fn headers_are_case_insensitive_and_multi_valued() {
+        let request = Request::new(Bytes::from_static(
+            b"GET / HTTP/1.1\r\nHost: a\r\nAccept: text/html\r\nX-Forwarded-For: 10.0.0.1\r\naccept: */*\r\n\r\n",
+        ))
+        .expect("parsing request");
+        let mut headers = request.headers;
+
+        assert_eq!(headers.get_str("x-forwarded-for").unwrap(), "10.0.0.1");
+        assert_eq!(headers.get("ACCEPT").unwrap(), &b"text/html"[..]);
+        assert_eq!(
+            headers.get_all("Accept").collect::<Vec<_>>(),
+            [&b"text/html"[..], b"*/*"]
+        );
+        assert_eq!(headers.get_combined("accept").unwrap(), &b"text/html, */*"[..]);
+        assert!(matches!(headers.get_combined("Cookie"), Err(HeaderError::NotFound)));
+
+        assert_eq!(
+            headers.insert("Accept", b"application/json".to_vec()),
+            Some(Bytes::from_static(b"text/html"))
+        );
+        headers.append("X-Forwarded-For", b"10.0.0.2".to_vec());
+        let names: Vec<&str> = headers.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["Host", "Accept", "X-Forwarded-For", "X-Forwarded-For"]);
+
+        assert_eq!(headers.remove("x-forwarded-for"), Some(Bytes::from_static(b"10.0.0.1")));
+        assert_eq!(headers.len(), 2);
+        assert!(!headers.contains("X-Forwarded-For"));
+    }
+
+    #[test]
+    fn typed_headers() {
+        let request = Request::new(Bytes::from_static(
+            b"GET / HTTP/1.1\r\nHost: example.com:8080\r\nAccept-Encoding: gzip\r\naccept-encoding: br;q=0.5\r\n\r\n",
+        ))
+        .expect("parsing request");
+        let mut headers = request.headers;
+
+        assert_eq!(headers.typed_get::<Host>().unwrap().port, Some(8080));
+        assert_eq!(headers.typed_get::<AcceptEncoding>().unwrap().0.len(), 2);
+        assert!(matches!(headers.typed_get::<Range>(), Err(HeaderError::NotFound)));
+
+        headers.typed_set(&ContentLength(5));
+        assert_eq!(headers.get_str("content-length").unwrap(), "5");
+    }
+
     #[test]
     fn parse_request_with_body() {
         let request = Request::new(Bytes::from_static(
-            b"POST /test HTTP/1.1\r\nContent-Length: 11\r\n\r\nHello World",
+            b"POST /test HTTP/1.1\r\nHost: localhost\r\nContent-Length: 11\r\n\r\nHello World",
         ))
         .expect("parsing request");
 
-        assert_eq!(request.version, 1);
-        assert_eq!(request.method, Some(Method::POST));
+        assert_eq!(request.version, Version::Http11);
+        assert_eq!(request.method, Method::POST);
         assert_eq!(request.path, String::from("/test"));
-        assert_eq!(request.headers.iter().count(), 1);
+        assert_eq!(request.headers.iter().count(), 2);
         assert_eq!(
-            request.headers.iter().find(|(k, _)| k.as_str() == "Content-Length"),
-            Some((&String::from("Content-Length"), &b"11"[..].to_vec()))
+            request.headers.iter().find(|(k, _)| *k == "Content-Length"),
+            Some(("Content-Length", &Bytes::from_static(b"11")))
         );
     }
 
+    #[rstest]
+    #[case::method(b"G@T /test HTTP/1.1\r\n\r\n", RequestError::Method, 0)]
+    #[case::uri(b"GET /te<st HTTP/1.1\r\n\r\n", RequestError::URI, 4)]
+    #[case::empty_uri(b"GET  HTTP/1.1\r\n\r\n", RequestError::URI, 4)]
+    #[case::version(b"GET /test HTTP/2.0\r\n\r\n", RequestError::Version, 10)]
+    #[case::short_version(b"GET /test HTTP\r\n\r\n", RequestError::Version, 10)]
+    #[case::header_name(b"GET / HTTP/1.1\r\nHost: a\r\nBad Header: b\r\n\r\n", RequestError::HeaderName, 25)]
+    #[case::header_value(b"GET / HTTP/1.1\r\nHost: a\x00b\r\n\r\n", RequestError::HeaderValue, 21)]
+    #[case::unterminated_headers(b"GET / HTTP/1.1\r\nHost: a\r\n", RequestError::NewLine, 25)]
+    #[case::content_length(
+        b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: x\r\n\r\n",
+        RequestError::HeaderContentLength,
+        55
+    )]
+    #[case::conflicting_content_length(
+        b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 1\r\ncontent-length: 2\r\n\r\nab",
+        RequestError::HeaderContentLength,
+        74
+    )]
+    #[case::signed_content_length(
+        b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: +5\r\n\r\nhello",
+        RequestError::HeaderContentLength,
+        56
+    )]
+    #[case::transfer_encoding(
+        b"POST / HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+        RequestError::TransferEncoding,
+        64
+    )]
+    #[case::transfer_encoding_with_content_length(
+        b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\nhello",
+        RequestError::TransferEncoding,
+        83
+    )]
+    #[case::incomplete_body(
+        b"POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 5\r\n\r\nab",
+        RequestError::IncompleteBody,
+        55
+    )]
+    #[case::missing_host(b"GET / HTTP/1.1\r\n\r\n", RequestError::Host, 16)]
+    #[case::duplicate_host(b"GET / HTTP/1.1\r\nHost: a\r\nHost: b\r\n\r\n", RequestError::Host, 16)]
+    #[case::invalid_host(b"GET / HTTP/1.1\r\nHost: a b\r\n\r\n", RequestError::Host, 16)]
+    fn strict_mode_reports_errors_with_offset(
+        #[case] raw: &'static [u8],
+        #[case] kind: RequestError,
+        #[case] offset: usize,
+    ) {
+        let error = Request::new(Bytes::from_static(raw)).expect_err("parsing request");
+        assert_eq!(error, kind.at(offset));
+    }
+
     #[test]
-    fn parse_request_with_invalid_method() {
-        _ = Request::new(Bytes::from_static(b"INVALID /test HTTP/1.1\r\n\r\n")).is_err();
+    fn strict_mode_trims_optional_whitespace() {
+        let request = Request::new(Bytes::from_static(
+            b"GET / HTTP/1.1\r\nHost:a\r\nAccept: \t*/* \r\n\r\n",
+        ))
+        .expect("parsing request");
+        assert_eq!(request.headers.get_str("Host").unwrap(), "a");
+        assert_eq!(request.headers.get_str("Accept").unwrap(), "*/*");
     }
 
     #[test]
-    fn parse_request_with_invalid_uri() {
-        _ = Request::new(Bytes::from_static(b"GET /test!@# HTTP/1.1\r\n\r\n")).is_err();
+    fn lenient_mode_rejects_bytes_outside_the_request_line_grammar() {
+        let raw = b"INVALID /te<st HTTP/1.1\r\n\r\n";
+        let request = Request::parse(Bytes::from_static(raw), ParseMode::Lenient).expect_err("parsing request");
+        assert_eq!(request.kind, RequestError::NewLine);
     }
 
     #[test]
-    fn parse_request_with_invalid_version() {
-        _ = Request::new(Bytes::from_static(b"GET /test HTTP/1.0\r\n\r\n")).is_err();
+    fn lenient_mode_defaults_invalid_method_and_skips_bad_headers() {
+        let raw = b"INV@LID /test HTTP/1.1\r\nHost: a\r\nBad Header: b\r\n\r\n";
+        let request = Request::parse(Bytes::from_static(raw), ParseMode::Lenient).expect("parsing request");
+        assert_eq!(request.method, Method::GET);
+        assert_eq!(request.path, "/test");
+        assert_eq!(request.version, Version::Http11);
+        assert_eq!(request.headers.iter().count(), 1);
     }
 }
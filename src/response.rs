@@ -1,12 +1,14 @@
 use bytes::{BufMut, BytesMut};
 use httpstatus::StatusCode;
-use std::collections::BTreeMap;
+
+use crate::{tokens, ContentType, Header, HeaderError, Version};
 
 #[derive(Debug)]
 pub struct Response {
+    version: Version,
     status_code: StatusCode,
     content_type: String,
-    headers: BTreeMap<String, String>,
+    headers: Vec<(String, String)>,
     body: BytesMut,
 }
 
@@ -25,48 +27,162 @@ impl From<Response> for Vec<u8> {
 impl Response {
     pub fn new() -> Self {
         Self {
+            version: Version::Http11,
             status_code: StatusCode::Ok,
             content_type: "text/plain".to_string(),
-            headers: BTreeMap::new(),
+            headers: Vec::new(),
             body: BytesMut::new(),
         }
     }
 
+    /// Version written in the status line. The server sets it to the request's version.
+    pub fn version(&mut self, version: Version) -> &mut Self {
+        self.version = version;
+        self
+    }
+
+    pub fn get_version(&self) -> Version {
+        self.version
+    }
+
     pub fn status_code(&mut self, status: StatusCode) -> &mut Self {
         self.status_code = status;
         self
     }
 
+    pub fn status(&self) -> &StatusCode {
+        &self.status_code
+    }
+
+    /// A value containing CR, LF or another control character is ignored, like in [`Response::set_header`].
+    /// Use [`Response::try_content_type`] to find out whether it was set.
     pub fn content_type(&mut self, content_type: &str) -> &mut Self {
-        self.content_type = content_type.to_string();
+        let _ = self.try_content_type(content_type);
         self
     }
 
+    pub fn try_content_type(&mut self, content_type: &str) -> Result<&mut Self, HeaderError> {
+        validate_header("Content-Type", content_type)?;
+        self.content_type = content_type.to_string();
+        Ok(self)
+    }
+
+    pub fn get_content_type(&self) -> &str {
+        &self.content_type
+    }
+
     pub fn write_body(&mut self, src: &[u8]) {
         self.body.put_slice(src)
     }
 
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
     pub fn clear(&mut self) {
         self.body.clear()
     }
 
+    /// Sets `key` to a single value in place of the first existing one, dropping the rest. Returns `Some(())`
+    /// if a value was replaced.
+    ///
+    /// A name that is not a valid token, or a value containing CR, LF or another control character, is
+    /// ignored so it cannot inject header lines. Use [`Response::try_set_header`] to find out whether it was
+    /// set, for example for values taken from the request.
     pub fn set_header(&mut self, key: &str, value: &str) -> Option<()> {
-        match self.headers.insert(key.to_string(), value.to_string()) {
-            Some(_) => Some(()),
-            _ => None,
+        self.try_set_header(key, value).ok().flatten()
+    }
+
+    pub fn try_set_header(&mut self, key: &str, value: &str) -> Result<Option<()>, HeaderError> {
+        validate_header(key, value)?;
+        let index = self.headers.iter().position(|(name, _)| name.eq_ignore_ascii_case(key));
+        let previous = self.remove_header(key);
+        let index = index.unwrap_or(self.headers.len());
+        self.headers.insert(index, (key.to_string(), value.to_string()));
+        Ok(previous.map(drop))
+    }
+
+    /// Adds a value for `key` after any existing ones, for headers such as `Set-Cookie` that cannot be
+    /// combined into one line. Invalid names and values are ignored, like in [`Response::set_header`].
+    pub fn append_header(&mut self, key: &str, value: &str) {
+        let _ = self.try_append_header(key, value);
+    }
+
+    pub fn try_append_header(&mut self, key: &str, value: &str) -> Result<(), HeaderError> {
+        validate_header(key, value)?;
+        self.headers.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    /// Removes every value of `key`. Returns the first one.
+    pub fn remove_header(&mut self, key: &str) -> Option<String> {
+        let mut removed = None;
+        self.headers.retain_mut(|(name, value)| {
+            if !name.eq_ignore_ascii_case(key) {
+                return true;
+            }
+            if removed.is_none() {
+                removed = Some(std::mem::take(value));
+            }
+            false
+        });
+        removed
+    }
+
+    /// The first value of `key`.
+    pub fn get_header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Decodes a typed header. `Content-Type` comes from [`Response::content_type`].
+    pub fn typed_get<H: Header>(&self) -> Result<H, HeaderError> {
+        if H::NAME == ContentType::NAME {
+            return H::decode(&self.content_type);
         }
+        H::decode(self.get_header(H::NAME).ok_or(HeaderError::NotFound)?)
     }
 
+    /// Sets a typed header, like [`Response::set_header`]. `Content-Type` is applied with
+    /// [`Response::content_type`]. An encoded value containing CR, LF or another control character is ignored.
+    pub fn typed_set<H: Header>(&mut self, header: &H) {
+        if H::NAME == ContentType::NAME {
+            self.content_type(&header.encode());
+        } else {
+            self.set_header(H::NAME, &header.encode());
+        }
+    }
+
+    /// Headers in the order they will be written, without `Content-Type` and `Content-Length`.
+    pub fn headers(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.headers.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Serializes the response. 1xx, 204 and 304 responses are written without a body or `Content-Length`.
     pub fn build(&self) -> Vec<u8> {
-        let mut response = b"HTTP/1.1 ".to_vec();
+        self.encode(true)
+    }
+
+    /// Serializes the response to a `HEAD` request: the headers of [`Response::build`], including the
+    /// `Content-Length` of the body, but not the body itself.
+    pub fn build_head(&self) -> Vec<u8> {
+        self.encode(false)
+    }
+
+    fn encode(&self, with_body: bool) -> Vec<u8> {
+        let mut response = self.version.as_str().as_bytes().to_vec();
+        response.put_slice(b" ");
 
         response.put_slice(self.status_code.as_u16().to_string().as_bytes());
         response.put_slice(b" ");
         response.put(self.status_code.reason_phrase().as_bytes());
         response.put_slice(b"\r\n");
 
-        let body = self.body.clone();
-        let content_length = body.len();
+        let status = self.status_code.as_u16();
+        let bodiless = (100..200).contains(&status) || status == 204 || status == 304;
+        let content_length = self.body.len();
 
         let content_type = if !self.content_type.is_empty() {
             self.content_type.clone()
@@ -74,25 +190,41 @@ impl Response {
             "text/plain".to_string()
         };
 
-        let mut headers = self.headers.clone();
-        headers.insert("Content-Type".to_string(), content_type);
-        headers.insert("Content-Length".to_string(), content_length.to_string());
-        for (key, val) in &headers {
+        let mut put_header = |key: &str, val: &str| {
             response.put_slice(key.as_bytes());
             response.put_slice(b": ");
             response.put_slice(val.as_bytes());
             response.put_slice(b"\r\n");
+        };
+        if !bodiless {
+            put_header("Content-Length", &content_length.to_string());
+        }
+        put_header("Content-Type", &content_type);
+        for (key, val) in &self.headers {
+            if !key.eq_ignore_ascii_case("Content-Length") && !key.eq_ignore_ascii_case("Content-Type") {
+                put_header(key, val);
+            }
         }
         response.put_slice(b"\r\n");
 
-        // parse body
-        let body = self.body.clone();
-        response.put(body);
+        if with_body && !bodiless {
+            response.put_slice(&self.body);
+        }
 
         response
     }
 }
 
+pub(crate) fn validate_header(key: &str, value: &str) -> Result<(), HeaderError> {
+    if key.is_empty() || !key.bytes().all(tokens::is_header_name_token) {
+        return Err(HeaderError::InvalidName);
+    }
+    if !value.bytes().all(tokens::is_header_value_token) {
+        return Err(HeaderError::InvalidValue);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -148,6 +280,104 @@ mod tests {
            )
     }
 
+    #[test]
+    fn headers_keep_insertion_order_and_repeat() {
+        let mut response = Response::new();
+        response.set_header("X-Zebra", "1");
+        response.append_header("Set-Cookie", "a=1");
+        response.append_header("Set-Cookie", "b=2");
+        response.set_header("Cache-Control", "no-store");
+        assert_eq!(response.set_header("x-zebra", "2"), Some(()));
+        assert_eq!(response.get_header("set-cookie"), Some("a=1"));
+        assert_eq!(
+            response.build(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nContent-Type: text/plain\r\nx-zebra: 2\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\nCache-Control: no-store\r\n\r\n"
+        );
+
+        assert_eq!(response.remove_header("SET-COOKIE"), Some("a=1".to_string()));
+        assert_eq!(response.headers().count(), 2);
+    }
+
+    #[test]
+    fn typed_headers() {
+        let mut response = Response::new();
+        response.typed_set(&ContentType::new("application/json").param("charset", "utf-8"));
+        response.typed_set(&crate::ETag(crate::EntityTag::strong("v1")));
+
+        assert_eq!(response.get_content_type(), "application/json; charset=utf-8");
+        assert_eq!(response.typed_get::<ContentType>().unwrap().charset(), Some("utf-8"));
+        assert_eq!(response.get_header("ETag"), Some("\"v1\""));
+        assert!(matches!(
+            response.typed_get::<crate::Vary>(),
+            Err(HeaderError::NotFound)
+        ));
+    }
+
+    #[test]
+    fn reject_header_injection() {
+        let mut response = Response::new();
+        assert!(matches!(
+            response.try_set_header("X-Name", "a\r\nSet-Cookie: evil=1"),
+            Err(HeaderError::InvalidValue)
+        ));
+        assert!(matches!(
+            response.try_append_header("X-Na\nme", "a"),
+            Err(HeaderError::InvalidName)
+        ));
+        assert!(matches!(
+            response.try_set_header("", "a"),
+            Err(HeaderError::InvalidName)
+        ));
+        assert_eq!(response.headers().count(), 0);
+    }
+
+    #[test]
+    fn reject_content_type_injection() {
+        let mut response = Response::new();
+        assert!(matches!(
+            response.try_content_type("text/html\r\nSet-Cookie: x=1"),
+            Err(HeaderError::InvalidValue)
+        ));
+        assert_eq!(response.get_content_type(), "text/plain");
+    }
+
+    #[test]
+    fn infallible_setters_ignore_invalid_values() {
+        let mut response = Response::new();
+        response.typed_set(&ContentType::new("text/html\r\nSet-Cookie: x=1"));
+        response.content_type("text/html\r\nSet-Cookie: x=1");
+        assert_eq!(response.set_header("Location", "/\r\n\r\n<script>"), None);
+        response.append_header("Set-Cookie", "a=1\r\nX: y");
+        assert_eq!(response.get_content_type(), "text/plain");
+        assert_eq!(response.headers().count(), 0);
+    }
+
+    #[test]
+    fn http_1_0_status_line() {
+        let mut response = Response::new();
+        response.version(Version::Http10).status_code(StatusCode::NoContent);
+        assert_eq!(
+            response.build(),
+            b"HTTP/1.0 204 No Content\r\nContent-Type: text/plain\r\n\r\n"
+        )
+    }
+
+    #[test]
+    fn bodiless_responses() {
+        let mut response = Response::new();
+        response.write_body(b"hello");
+        assert_eq!(
+            response.build_head(),
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nContent-Type: text/plain\r\n\r\n"
+        );
+
+        response.status_code(StatusCode::NotModified);
+        assert_eq!(
+            response.build(),
+            b"HTTP/1.1 304 Not Modified\r\nContent-Type: text/plain\r\n\r\n"
+        );
+    }
+
     #[test]
     fn response_with_body_content() {
         let mut response = Response::new();
@@ -1,11 +1,87 @@
-use crate::{Context, Method};
-use std::{collections::HashMap, future::Future, pin::Pin};
+use crate::{panic::CatchUnwind, Context, HandlerPanic, Method, Middleware, Next, Timeout};
+use httpstatus::StatusCode;
+use std::{collections::HashMap, future::Future, pin::Pin, sync::Arc, time::Duration};
 
-pub type HandlerFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
-pub type Handler = Box<dyn Fn(&mut Context) -> HandlerFuture + Send + Sync + 'static>;
+pub type HandlerFuture<'a> = Pin<Box<dyn Future<Output = ()> + Send + 'a>>;
 
+/// A request handler. Implemented for any `async fn(&mut Context)`.
+pub trait Handler: Send + Sync {
+    fn call<'a>(&'a self, ctx: &'a mut Context) -> HandlerFuture<'a>;
+}
+
+/// Ties the returned future to the lifetime of the borrowed `Context`, which a plain
+/// `Fn(&mut Context) -> Fut` bound cannot express.
+pub trait HandlerFn<'a>: Send + Sync {
+    type Future: Future<Output = ()> + Send + 'a;
+
+    fn invoke(&self, ctx: &'a mut Context) -> Self::Future;
+}
+
+impl<'a, F, Fut> HandlerFn<'a> for F
+where
+    F: Fn(&'a mut Context) -> Fut + Send + Sync,
+    Fut: Future<Output = ()> + Send + 'a,
+{
+    type Future = Fut;
+
+    fn invoke(&self, ctx: &'a mut Context) -> Self::Future {
+        self(ctx)
+    }
+}
+
+impl<F> Handler for F
+where
+    F: for<'a> HandlerFn<'a>,
+{
+    fn call<'a>(&'a self, ctx: &'a mut Context) -> HandlerFuture<'a> {
+        Box::pin(self.invoke(ctx))
+    }
+}
+
+#[derive(Clone)]
+pub struct Route {
+    method: Method,
+    path: String,
+    handler: Arc<dyn Handler>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    timeout: Option<Duration>,
+}
+
+impl Route {
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Adds middleware that only runs for this route, inside any router and server middleware.
+    pub fn middleware(&mut self, middleware: impl Middleware + 'static) -> &mut Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Overrides the handler timeout set by a [`Timeout`] layer, or sets one for this route only.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// Routes are kept behind an `Arc` so cloning a router is cheap. Registering routes on a clone copies the
+/// table first and leaves the original untouched.
+#[derive(Clone)]
 pub struct Router {
-    routes: HashMap<String, Handler>,
+    inner: Arc<RouterInner>,
+}
+
+#[derive(Clone)]
+struct RouterInner {
+    routes: Vec<Route>,
+    nested: Vec<(String, Router)>,
+    middleware: Vec<Arc<dyn Middleware>>,
+    error_handlers: HashMap<u16, Arc<dyn Handler>>,
 }
 
 impl Default for Router {
@@ -16,73 +92,173 @@ impl Default for Router {
 
 impl Router {
     pub fn new() -> Self {
-        Router { routes: HashMap::new() }
+        Router {
+            inner: Arc::new(RouterInner {
+                routes: Vec::new(),
+                nested: Vec::new(),
+                middleware: Vec::new(),
+                error_handlers: HashMap::new(),
+            }),
+        }
+    }
+
+    pub fn get(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Route {
+        self.add_route(Method::GET, path, handler)
+    }
+
+    pub fn post(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Route {
+        self.add_route(Method::POST, path, handler)
+    }
+
+    pub fn put(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Route {
+        self.add_route(Method::PUT, path, handler)
+    }
+
+    /// Paths without a `HEAD` route answer `HEAD` with their `GET` route, and the server leaves out the body.
+    pub fn head(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Route {
+        self.add_route(Method::HEAD, path, handler)
+    }
+
+    pub fn patch(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Route {
+        self.add_route(Method::PATCH, path, handler)
     }
 
-    pub fn get(&mut self, path: &str, handler: Handler) {
-        self.add_route(Method::GET, path, handler);
+    pub fn delete(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Route {
+        self.add_route(Method::DELETE, path, handler)
     }
 
-    pub fn post(&mut self, path: &str, handler: Handler) {
-        self.add_route(Method::POST, path, handler);
+    pub fn trace(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Route {
+        self.add_route(Method::TRACE, path, handler)
     }
 
-    pub fn put(&mut self, path: &str, handler: Handler) {
-        self.add_route(Method::PUT, path, handler);
+    pub fn connect(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Route {
+        self.add_route(Method::CONNECT, path, handler)
     }
 
-    pub fn delete(&mut self, path: &str, handler: Handler) {
-        self.add_route(Method::DELETE, path, handler);
+    pub fn options(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Route {
+        self.add_route(Method::OPTIONS, path, handler)
     }
 
-    pub fn trace(&mut self, path: &str, handler: Handler) {
-        self.add_route(Method::TRACE, path, handler);
+    pub fn any(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Route {
+        self.add_route(Method::ANY, path, handler)
     }
 
-    pub fn connect(&mut self, path: &str, handler: Handler) {
-        self.add_route(Method::CONNECT, path, handler);
+    /// Registers a handler. Registering the same method and path again replaces the previous route.
+    pub fn add_route(&mut self, method: Method, path: &str, handler: impl Handler + 'static) -> &mut Route {
+        let route = Route {
+            method,
+            path: path.to_string(),
+            handler: Arc::new(handler),
+            middleware: Vec::new(),
+            timeout: None,
+        };
+
+        let routes = &mut Arc::make_mut(&mut self.inner).routes;
+        let index = match routes.iter().position(|r| r.method == route.method && r.path == path) {
+            Some(index) => {
+                routes[index] = route;
+                index
+            }
+            None => {
+                routes.push(route);
+                routes.len() - 1
+            }
+        };
+        &mut routes[index]
     }
 
-    pub fn options(&mut self, path: &str, handler: Handler) {
-        self.add_route(Method::OPTIONS, path, handler);
+    /// Adds middleware that runs for every request reaching this router, including unmatched ones.
+    pub fn middleware(&mut self, middleware: impl Middleware + 'static) -> &mut Self {
+        Arc::make_mut(&mut self.inner).middleware.push(Arc::new(middleware));
+        self
     }
 
-    pub fn any(&mut self, path: &str, handler: Handler) {
-        self.add_route(Method::ANY, path, handler);
+    /// Mounts `router` under `prefix`. Its routes are matched against the rest of the path and its
+    /// middleware only runs for requests under the prefix.
+    pub fn nest(&mut self, prefix: &str, router: Router) -> &mut Self {
+        Arc::make_mut(&mut self.inner)
+            .nested
+            .push((prefix.trim_end_matches('/').to_string(), router));
+        self
     }
 
-    pub fn add_route(&mut self, method: Method, path: &str, handler: Handler) {
-        let key = format!("{} {}", method, path);
-        self.routes.insert(key, handler);
+    /// Builds a nested router in place, see [`Router::nest`].
+    pub fn group(&mut self, prefix: &str, build: impl FnOnce(&mut Router)) -> &mut Self {
+        let mut router = Router::new();
+        build(&mut router);
+        self.nest(prefix, router)
+    }
+
+    /// Handles requests that match no route, shorthand for a 404 [`Router::error_handler`].
+    pub fn fallback(&mut self, handler: impl Handler + 'static) -> &mut Self {
+        self.error_handler(StatusCode::NotFound, handler)
+    }
+
+    /// Replaces the default response for 404 (no route), 405 (path matched with another method) or
+    /// 500 (a handler panicked, the panic is available as `ctx.get::<HandlerPanic>()`). The status is
+    /// set before the handler runs. Nested routers without their own handler use their parent's.
+    pub fn error_handler(&mut self, status: StatusCode, handler: impl Handler + 'static) -> &mut Self {
+        Arc::make_mut(&mut self.inner)
+            .error_handlers
+            .insert(status.as_u16(), Arc::new(handler));
+        self
     }
 
     pub async fn route(&self, ctx: &mut Context, method: &Method, path: &str) {
-        let (handler, params) = self.match_route(method, path);
-        if let Some(handler) = handler {
-            ctx.set_path(path.to_string());
-            ctx.set_path_params(params);
+        let dispatch = Dispatch {
+            router: self,
+            parent: None,
+            method,
+            path,
+            rest: path,
+        };
+        Next::new(&self.inner.middleware, &dispatch).run(ctx).await;
+    }
 
-            handler(ctx).await;
-        } else {
-            let mut response = ctx.response.lock().await;
-            response.status_code(httpstatus::StatusCode::NotFound);
-            response.write_body(b"Not Found");
-        }
+    /// RFC 9110 section 9.3.2: `HEAD` falls back to the `GET` route when there is no route of its own.
+    fn match_route(&self, method: &Method, path: &str) -> Option<(&Route, HashMap<String, String>)> {
+        let find = |method: &Method| {
+            self.inner
+                .routes
+                .iter()
+                .filter(|route| &route.method == method || route.method == Method::ANY)
+                .find_map(|route| self.match_path(&route.path, path).map(|params| (route, params)))
+        };
+        find(method).or_else(|| match method {
+            Method::HEAD => find(&Method::GET),
+            _ => None,
+        })
     }
 
-    fn match_route(&self, method: &Method, path: &str) -> (Option<&Handler>, HashMap<String, String>) {
-        for (route, handler) in &self.routes {
-            let parts: Vec<&str> = route.splitn(2, ' ').collect();
-            if parts.len() == 2 {
-                let route_method = Method::try_from(parts[0]).unwrap_or(Method::GET);
-                if &route_method == method || route_method == Method::ANY {
-                    if let Some(params) = self.match_path(parts[1], path) {
-                        return (Some(handler), params);
-                    }
+    /// Methods with a route for `path`, for the `Allow` header of a 405.
+    fn allowed_methods(&self, path: &str) -> Vec<&Method> {
+        let mut methods = Vec::new();
+        for route in &self.inner.routes {
+            if !methods.contains(&&route.method) && self.match_path(&route.path, path).is_some() {
+                methods.push(&route.method);
+                if route.method == Method::GET && !methods.contains(&&Method::HEAD) {
+                    methods.push(&Method::HEAD);
                 }
             }
         }
-        (None, HashMap::new())
+        methods
+    }
+
+    /// Whether any route here or in a nested router is registered for `method`.
+    fn recognizes(&self, method: &Method) -> bool {
+        self.inner.routes.iter().any(|route| &route.method == method)
+            || self.inner.nested.iter().any(|(_, router)| router.recognizes(method))
+    }
+
+    fn match_nested<'p>(&self, path: &'p str) -> Option<(&Router, &'p str)> {
+        self.inner.nested.iter().find_map(|(prefix, router)| {
+            let rest = path.strip_prefix(prefix.as_str())?;
+            match rest {
+                "" => Some((router, "/")),
+                _ if rest.starts_with('/') => Some((router, rest)),
+                _ => None,
+            }
+        })
     }
 
     fn match_path(&self, route: &str, path: &str) -> Option<HashMap<String, String>> {
@@ -105,3 +281,330 @@ impl Router {
         Some(params)
     }
 }
+
+struct Dispatch<'r> {
+    router: &'r Router,
+    parent: Option<&'r Dispatch<'r>>,
+    method: &'r Method,
+    path: &'r str,
+    rest: &'r str,
+}
+
+impl Dispatch<'_> {
+    async fn dispatch(&self, ctx: &mut Context) {
+        let router = self.router;
+        if let Some((route, params)) = router.match_route(self.method, self.rest) {
+            ctx.set_path(self.path.to_string());
+            ctx.set_path_params(params);
+            self.run_route(ctx, route).await;
+        } else if let Some((nested, rest)) = router.match_nested(self.rest) {
+            let dispatch = Dispatch {
+                router: nested,
+                parent: Some(self),
+                method: self.method,
+                path: self.path,
+                rest,
+            };
+            Next::new(&nested.inner.middleware, &dispatch).run(ctx).await;
+        } else {
+            let allowed = router.allowed_methods(self.rest);
+            if matches!(self.method, Method::Extension(_)) && !self.root().recognizes(self.method) {
+                self.error(ctx, StatusCode::NotImplemented, b"Not Implemented").await;
+            } else if allowed.is_empty() {
+                self.error(ctx, StatusCode::NotFound, b"Not Found").await;
+            } else {
+                let allow: Vec<&str> = allowed.iter().map(|method| method.as_str()).collect();
+                ctx.response_mut().set_header("Allow", &allow.join(", "));
+                self.error(ctx, StatusCode::MethodNotAllowed, b"Method Not Allowed")
+                    .await;
+            }
+        }
+    }
+
+    /// Runs the route's middleware and handler. Panics are only caught here when a 500 handler is
+    /// registered, otherwise they are left to the server.
+    async fn run_route(&self, ctx: &mut Context, route: &Route) {
+        let timeout = Timeout::for_route(ctx, route.timeout);
+        let Some(handler) = self.error_handler(StatusCode::InternalServerError) else {
+            let next = Next::new(&route.middleware, route.handler.as_ref());
+            return match timeout {
+                Some(timeout) => timeout.run(ctx, next).await,
+                None => next.run(ctx).await,
+            };
+        };
+
+        let result = CatchUnwind::new(Box::pin(async {
+            let next = Next::new(&route.middleware, route.handler.as_ref());
+            match timeout {
+                Some(timeout) => timeout.run(ctx, next).await,
+                None => next.run(ctx).await,
+            }
+        }))
+        .await;
+
+        if let Err(caught) = result {
+            let report = HandlerPanic {
+                message: caught.message,
+                location: caught.location,
+                method: self.method.clone(),
+                path: self.path.to_string(),
+            };
+            ctx.insert(report);
+            *ctx.response_mut() = crate::Response::new();
+            ctx.response_mut().status_code(StatusCode::InternalServerError);
+            handler.call(ctx).await;
+        }
+    }
+
+    async fn error(&self, ctx: &mut Context, status: StatusCode, body: &[u8]) {
+        ctx.set_path(self.path.to_string());
+        let handler = self.error_handler(status.clone());
+        ctx.response_mut().status_code(status);
+        match handler {
+            Some(handler) => handler.call(ctx).await,
+            None => ctx.response_mut().write_body(body),
+        }
+    }
+
+    fn root(&self) -> &Router {
+        let mut dispatch = self;
+        while let Some(parent) = dispatch.parent {
+            dispatch = parent;
+        }
+        dispatch.router
+    }
+
+    fn error_handler(&self, status: StatusCode) -> Option<&Arc<dyn Handler>> {
+        let status = status.as_u16();
+        let mut dispatch = Some(self);
+        while let Some(current) = dispatch {
+            if let Some(handler) = current.router.inner.error_handlers.get(&status) {
+                return Some(handler);
+            }
+            dispatch = current.parent;
+        }
+        None
+    }
+}
+
+impl Handler for Dispatch<'_> {
+    fn call<'a>(&'a self, ctx: &'a mut Context) -> HandlerFuture<'a> {
+        Box::pin(self.dispatch(ctx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Request, Response};
+    use bytes::Bytes;
+
+    async fn route(router: &Router, method: Method, path: &str) -> Context {
+        let request = Request::new(Bytes::from(format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            method, path
+        )))
+        .expect("parsing request");
+        let mut ctx = Context::new(request, Response::new());
+        router.route(&mut ctx, &method, path).await;
+        ctx
+    }
+
+    async fn handler(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"handler;");
+    }
+
+    async fn user_handler(ctx: &mut Context) {
+        let id = ctx.path_params.get("id").cloned().unwrap_or_default();
+        ctx.response_mut().write_body(id.as_bytes());
+    }
+
+    async fn tag_router(ctx: &mut Context, next: Next<'_>) {
+        ctx.response_mut().write_body(b"router;");
+        next.run(ctx).await;
+    }
+
+    async fn tag_group(ctx: &mut Context, next: Next<'_>) {
+        ctx.response_mut().write_body(b"group;");
+        next.run(ctx).await;
+    }
+
+    async fn tag_route(ctx: &mut Context, next: Next<'_>) {
+        ctx.response_mut().write_body(b"route;");
+        next.run(ctx).await;
+    }
+
+    #[tokio::test]
+    async fn match_path_params() {
+        let mut router = Router::new();
+        router.get("/users/:id", user_handler);
+
+        let ctx = route(&router, Method::GET, "/users/42").await;
+        assert_eq!(ctx.response().body(), b"42");
+        assert_eq!(ctx.path, "/users/42");
+    }
+
+    async fn custom_not_found(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"custom 404");
+    }
+
+    async fn api_not_found(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"api 404");
+    }
+
+    async fn method_not_allowed(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"custom 405");
+    }
+
+    async fn internal_error(ctx: &mut Context) {
+        let message = ctx.get::<HandlerPanic>().map(|panic| panic.message.clone());
+        ctx.response_mut().write_body(message.unwrap_or_default().as_bytes());
+    }
+
+    async fn boom(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"partial");
+        panic!("handler failed");
+    }
+
+    #[tokio::test]
+    async fn not_found() {
+        let mut router = Router::new();
+        router.get("/", handler);
+
+        let ctx = route(&router, Method::GET, "/missing").await;
+        assert_eq!(ctx.response().status(), &StatusCode::NotFound);
+        assert_eq!(ctx.response().body(), b"Not Found");
+    }
+
+    #[tokio::test]
+    async fn patch_and_extension_methods() {
+        let propfind = Method::Extension("PROPFIND".to_string());
+        let mut router = Router::new();
+        router.patch("/users/:id", user_handler);
+        router.group("/dav", |dav| {
+            dav.add_route(propfind.clone(), "/files", handler);
+        });
+
+        let ctx = route(&router, Method::PATCH, "/users/7").await;
+        assert_eq!(ctx.response().body(), b"7");
+        let ctx = route(&router, propfind.clone(), "/dav/files").await;
+        assert_eq!(ctx.response().body(), b"handler;");
+
+        let ctx = route(&router, propfind, "/users/7").await;
+        assert_eq!(ctx.response().status(), &StatusCode::MethodNotAllowed);
+        assert_eq!(ctx.response().get_header("Allow"), Some("PATCH"));
+        let ctx = route(&router, Method::Extension("MKCOL".to_string()), "/dav/files").await;
+        assert_eq!(ctx.response().status(), &StatusCode::NotImplemented);
+    }
+
+    #[tokio::test]
+    async fn head_falls_back_to_get() {
+        let mut router = Router::new();
+        router.get("/users/:id", user_handler);
+        router.head("/explicit", handler);
+        router.get("/explicit", user_handler);
+
+        let ctx = route(&router, Method::HEAD, "/users/7").await;
+        assert_eq!(ctx.response().status(), &StatusCode::Ok);
+        assert_eq!(ctx.response().body(), b"7");
+        let ctx = route(&router, Method::HEAD, "/explicit").await;
+        assert_eq!(ctx.response().body(), b"handler;");
+    }
+
+    #[tokio::test]
+    async fn method_not_allowed_lists_allowed_methods() {
+        let mut router = Router::new();
+        router.get("/users/:id", user_handler);
+        router.delete("/users/:id", handler);
+
+        let ctx = route(&router, Method::POST, "/users/1").await;
+        assert_eq!(ctx.response().status(), &StatusCode::MethodNotAllowed);
+        assert_eq!(ctx.response().get_header("Allow"), Some("GET, HEAD, DELETE"));
+        assert_eq!(ctx.response().body(), b"Method Not Allowed");
+
+        router.error_handler(StatusCode::MethodNotAllowed, method_not_allowed);
+        let ctx = route(&router, Method::POST, "/users/1").await;
+        assert_eq!(ctx.response().status(), &StatusCode::MethodNotAllowed);
+        assert_eq!(ctx.response().body(), b"custom 405");
+    }
+
+    #[tokio::test]
+    async fn fallback_is_inherited_by_nested_routers() {
+        let mut router = Router::new();
+        router.fallback(custom_not_found);
+        router.group("/api", |api| {
+            api.get("/users/:id", user_handler);
+        });
+        router.group("/admin", |admin| {
+            admin.fallback(api_not_found);
+        });
+
+        let ctx = route(&router, Method::GET, "/missing").await;
+        assert_eq!(ctx.response().status(), &StatusCode::NotFound);
+        assert_eq!(ctx.response().body(), b"custom 404");
+        assert_eq!(ctx.path, "/missing");
+
+        let ctx = route(&router, Method::GET, "/api/missing").await;
+        assert_eq!(ctx.response().body(), b"custom 404");
+
+        let ctx = route(&router, Method::GET, "/admin/missing").await;
+        assert_eq!(ctx.response().status(), &StatusCode::NotFound);
+        assert_eq!(ctx.response().body(), b"api 404");
+    }
+
+    #[tokio::test]
+    async fn internal_error_handler_recovers_panics() {
+        let mut router = Router::new();
+        router.error_handler(StatusCode::InternalServerError, internal_error);
+        router.get("/boom", boom);
+
+        let ctx = route(&router, Method::GET, "/boom").await;
+        assert_eq!(ctx.response().status(), &StatusCode::InternalServerError);
+        assert_eq!(ctx.response().body(), b"handler failed");
+        assert_eq!(
+            ctx.get::<HandlerPanic>().map(|panic| panic.path.as_str()),
+            Some("/boom")
+        );
+    }
+
+    #[tokio::test]
+    async fn middleware_order() {
+        let mut router = Router::new();
+        router.middleware(tag_router);
+        router.get("/plain", handler);
+        router.get("/wrapped", handler).middleware(tag_route);
+
+        let ctx = route(&router, Method::GET, "/plain").await;
+        assert_eq!(ctx.response().body(), b"router;handler;");
+
+        let ctx = route(&router, Method::GET, "/wrapped").await;
+        assert_eq!(ctx.response().body(), b"router;route;handler;");
+
+        let ctx = route(&router, Method::GET, "/missing").await;
+        assert_eq!(ctx.response().body(), b"router;Not Found");
+    }
+
+    #[tokio::test]
+    async fn group_middleware_only_applies_under_prefix() {
+        let mut router = Router::new();
+        router.get("/", handler);
+        router.group("/api", |api| {
+            api.middleware(tag_group);
+            api.get("/", handler);
+            api.get("/users/:id", user_handler).middleware(tag_route);
+        });
+
+        let ctx = route(&router, Method::GET, "/").await;
+        assert_eq!(ctx.response().body(), b"handler;");
+
+        let ctx = route(&router, Method::GET, "/api").await;
+        assert_eq!(ctx.response().body(), b"group;handler;");
+
+        let ctx = route(&router, Method::GET, "/api/users/7").await;
+        assert_eq!(ctx.response().body(), b"group;route;7");
+        assert_eq!(ctx.path, "/api/users/7");
+
+        let ctx = route(&router, Method::GET, "/apix").await;
+        assert_eq!(ctx.response().status(), &StatusCode::NotFound);
+    }
+}
@@ -1,16 +1,131 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use bytes::BytesMut;
-use std::{net::SocketAddr, sync::Arc};
+use httpstatus::StatusCode;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tokio::{
-    io::AsyncReadExt,
+    io::{AsyncReadExt, AsyncWriteExt},
     net::{TcpListener, TcpStream},
-    sync::Mutex,
 };
 
-use crate::{Context, Request, Response, Router};
+use crate::{
+    body_length, panic::CatchUnwind, Context, Extensions, Handler, HandlerFuture, HandlerPanic, Host, Limits, Method,
+    Middleware, Next, ParseMode, Rejection, Request, RequestError, Response, Router, Version,
+};
+
+pub type PanicHook = Arc<dyn Fn(&HandlerPanic) + Send + Sync>;
+
+/// How long a rejected connection is drained before it is closed.
+const LINGER: Duration = Duration::from_secs(1);
 
 pub struct Server {
-    router: Arc<Mutex<Router>>,
+    router: Router,
+    middleware: Vec<Arc<dyn Middleware>>,
+    settings: Settings,
+    #[cfg(feature = "tower")]
+    service: Option<crate::service::BoxedService>,
+}
+
+/// How each connection reads and answers requests, shared by the connection tasks.
+#[derive(Clone)]
+struct Settings {
+    on_panic: Option<PanicHook>,
+    max_body_size: usize,
+    parse_mode: ParseMode,
+    limits: Limits,
+    read_timeout: Duration,
+    allowed_hosts: Option<Arc<[String]>>,
+}
+
+struct App {
+    router: Router,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl Handler for App {
+    /// Routes the request, answering 500 if the route panics so the server middleware still sees the
+    /// response, for example to log it or tag it with a request ID.
+    fn call<'a>(&'a self, ctx: &'a mut Context) -> HandlerFuture<'a> {
+        Box::pin(async move {
+            let method = ctx.request().method.clone();
+            let path = ctx.request().path.clone();
+            let result = CatchUnwind::new(Box::pin(self.router.route(ctx, &method, &path))).await;
+
+            if let Err(caught) = result {
+                ctx.insert(HandlerPanic {
+                    message: caught.message,
+                    location: caught.location,
+                    method,
+                    path: path.to_string(),
+                });
+                *ctx.response_mut() = Server::internal_error();
+            }
+        })
+    }
+}
+
+#[derive(Clone)]
+enum Endpoint {
+    App(Arc<App>),
+    #[cfg(feature = "tower")]
+    Service(crate::service::BoxedService),
+}
+
+impl Endpoint {
+    /// The server middleware, told about requests rejected before they reach it.
+    fn middleware(&self) -> &[Arc<dyn Middleware>] {
+        match self {
+            Endpoint::App(app) => &app.middleware,
+            #[cfg(feature = "tower")]
+            Endpoint::Service(_) => &[],
+        }
+    }
+
+    async fn respond(&mut self, request: Request, on_panic: Option<&PanicHook>) -> Response {
+        match self {
+            Endpoint::App(app) => {
+                let mut ctx = Context::new(request, Response::default());
+                Next::new(&app.middleware, app.as_ref()).run(&mut ctx).await;
+                // A panic already answered by a router's 500 handler is still reported.
+                if let Some(report) = ctx.remove::<HandlerPanic>() {
+                    report_panic(on_panic, &report);
+                }
+                ctx.into_response()
+            }
+            #[cfg(feature = "tower")]
+            Endpoint::Service(service) => {
+                let method = request.method.clone();
+                let path = request.path.to_string();
+                let respond = crate::service::respond(service, request);
+                match SERVICE_PANIC_HOOK.scope(on_panic.cloned(), respond).await {
+                    Ok(response) => response,
+                    // A service error, such as a tower timeout, is reported like a panic.
+                    Err(e) => {
+                        let report = HandlerPanic {
+                            message: e.to_string(),
+                            location: None,
+                            method,
+                            path,
+                        };
+                        report_panic(on_panic, &report);
+                        Server::internal_error()
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tower")]
+tokio::task_local! {
+    /// The [`Server::on_panic`] hook while a tower service runs, for panics a router answers itself.
+    static SERVICE_PANIC_HOOK: Option<PanicHook>;
+}
+
+/// Reports a panic a router served as a tower service answered with its 500 handler, through the hook of
+/// the server running it. Outside a server the 500 handler is the only place the panic is seen.
+#[cfg(feature = "tower")]
+pub(crate) fn report_service_panic(report: &HandlerPanic) {
+    let _ = SERVICE_PANIC_HOOK.try_with(|on_panic| report_panic(on_panic.as_ref(), report));
 }
 
 impl Default for Server {
@@ -22,42 +137,718 @@ impl Default for Server {
 impl Server {
     pub fn new() -> Self {
         Server {
-            router: Arc::new(Mutex::new(Router::new())),
+            router: Router::new(),
+            middleware: Vec::new(),
+            settings: Settings {
+                on_panic: None,
+                max_body_size: 10 * 1024 * 1024,
+                parse_mode: ParseMode::Strict,
+                limits: Limits::default(),
+                read_timeout: Duration::from_secs(30),
+                allowed_hosts: None,
+            },
+            #[cfg(feature = "tower")]
+            service: None,
         }
     }
 
     pub fn apply(&mut self, router: Router) {
-        self.router = Arc::new(Mutex::new(router));
+        self.router = router;
+    }
+
+    /// Adds middleware that wraps every request, before routing.
+    pub fn middleware(&mut self, middleware: impl Middleware + 'static) -> &mut Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Called when a handler or middleware panics, or a tower service set with [`Server::serve`] fails,
+    /// instead of the report printed to stderr. The client
+    /// receives a 500 response either way. The process panic hook still runs for the panic, unless
+    /// [`Server::capture_panic_locations`] is enabled.
+    pub fn on_panic(&mut self, hook: impl Fn(&HandlerPanic) + Send + Sync + 'static) -> &mut Self {
+        self.settings.on_panic = Some(Arc::new(hook));
+        self
+    }
+
+    /// Wraps the process panic hook so panics caught while serving requests record their
+    /// [`HandlerPanic::location`] and are not printed by it. Every other panic is passed on to the hook that
+    /// was set before. Off by default, since the panic hook is shared by the whole process.
+    pub fn capture_panic_locations(&mut self) -> &mut Self {
+        crate::panic::capture_locations();
+        self
+    }
+
+    /// Largest request body accepted, by `Content-Length`. Larger requests are answered with 413 and the
+    /// connection is closed. Defaults to 10 MiB.
+    pub fn max_body_size(&mut self, max_body_size: usize) -> &mut Self {
+        self.settings.max_body_size = max_body_size;
+        self
+    }
+
+    /// How requests are parsed. [`ParseMode::Strict`] by default, which answers malformed requests with
+    /// 400 (or a more specific status) instead of guessing.
+    pub fn parse_mode(&mut self, mode: ParseMode) -> &mut Self {
+        self.settings.parse_mode = mode;
+        self
+    }
+
+    /// Limits on the request line and header section, checked while reading and parsing. Requests over
+    /// them are answered with 414 or 431 and the connection is closed.
+    pub fn limits(&mut self, limits: Limits) -> &mut Self {
+        self.settings.limits = limits;
+        self
+    }
+
+    /// How long a connection may take to send a whole request, and how long it may stay idle between
+    /// requests. An idle connection is closed; a partly sent request is answered with 408 first. Defaults
+    /// to 30 seconds.
+    pub fn read_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.settings.read_timeout = timeout;
+        self
+    }
+
+    /// Only accepts requests for these hostnames, compared without the port and case-insensitively. A name
+    /// starting with `.` also accepts its subdomains. Other requests, including ones without exactly one `Host`
+    /// in either [`ParseMode`], are answered with 400 and the connection is closed. Every host is accepted by default.
+    pub fn allowed_hosts<I, S>(&mut self, hosts: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let hosts = hosts
+            .into_iter()
+            .map(|host| host.as_ref().trim_end_matches('.').to_ascii_lowercase())
+            .collect();
+        self.settings.allowed_hosts = Some(hosts);
+        self
+    }
+
+    /// Serves requests with a tower service instead of the applied router. Server middleware is not run
+    /// for a tower service; wrap it with tower layers instead.
+    #[cfg(feature = "tower")]
+    pub fn serve<S>(&mut self, service: S) -> &mut Self
+    where
+        S: tower::Service<Request, Response = Response> + Clone + Send + 'static,
+        S::Error: Into<tower::BoxError>,
+        S::Future: Send + 'static,
+    {
+        self.service = Some(crate::service::boxed(service));
+        self
     }
 
     pub async fn listen(&mut self, address: SocketAddr) -> Result<()> {
         let listener = TcpListener::bind(address).await?;
         println!("Server started on {}", address);
-        let router = self.router.clone();
+        let endpoint = self.endpoint();
+        let settings = Arc::new(self.settings.clone());
         loop {
-            let (stream, _addr) = listener.accept().await?;
-            let router = router.clone();
+            let (stream, addr) = listener.accept().await?;
+            let endpoint = endpoint.clone();
+            let settings = settings.clone();
             tokio::spawn(async move {
-                if let Err(e) = Server::handle_connection(stream, router).await {
+                if let Err(e) = Server::handle_connection(stream, addr, endpoint, settings).await {
                     eprintln!("Error processing request: {}", e);
                 }
             });
         }
     }
 
-    async fn handle_connection(mut socket: TcpStream, router: Arc<Mutex<Router>>) -> Result<()> {
+    fn endpoint(&self) -> Endpoint {
+        #[cfg(feature = "tower")]
+        if let Some(service) = &self.service {
+            return Endpoint::Service(service.clone());
+        }
+
+        Endpoint::App(Arc::new(App {
+            router: self.router.clone(),
+            middleware: self.middleware.clone(),
+        }))
+    }
+
+    /// Serves requests on one connection until the client closes it or asks for `Connection: close`.
+    /// HTTP/1.0 clients get a response of the same version, and the connection is only kept open when
+    /// they send `Connection: keep-alive`. Bodies are always framed with `Content-Length`, never chunked, and
+    /// are not sent for `HEAD` requests or 1xx, 204 and 304 responses.
+    /// A request that fails to parse is answered with its error status and ends the connection, since
+    /// the rest of the stream can no longer be framed reliably.
+    async fn handle_connection(
+        mut socket: TcpStream,
+        addr: SocketAddr,
+        mut endpoint: Endpoint,
+        settings: Arc<Settings>,
+    ) -> Result<()> {
         let mut bytes = BytesMut::new();
-        socket.read_buf(&mut bytes).await?;
+        loop {
+            let read = Server::read_request(&mut socket, &mut bytes, &settings);
+            let length = match tokio::time::timeout(settings.read_timeout, read).await {
+                Err(_) if bytes.is_empty() => return Ok(()),
+                Err(_) => return Server::reject(&mut socket, addr, endpoint.middleware(), RequestError::Timeout).await,
+                Ok(Ok(Some(length))) => length,
+                Ok(Ok(None)) => return Ok(()),
+                Ok(Err(e)) => match e.downcast::<RequestError>() {
+                    Ok(error) => return Server::reject(&mut socket, addr, endpoint.middleware(), error).await,
+                    Err(e) => return Err(e),
+                },
+            };
 
-        let buf = bytes.freeze();
-        let request = Request::new(buf)?;
-        let response = Response::default();
-        let path = request.path.clone();
-        let method = request.method;
-        let mut ctx = Context::new(request, response);
+            let buf = bytes.split_to(length).freeze();
+            let mut request = match Request::parse_prefix(buf, settings.parse_mode, &settings.limits) {
+                // A strict parse must end exactly where the request was framed, or the two disagree on where
+                // the next request starts. A lenient one leaves the header lines after a malformed one unread.
+                Ok((_, consumed)) if consumed != length && settings.parse_mode == ParseMode::Strict => {
+                    return Server::reject(&mut socket, addr, endpoint.middleware(), RequestError::Framing).await;
+                }
+                Ok((request, _)) => request,
+                Err(error) => return Server::reject(&mut socket, addr, endpoint.middleware(), error.kind).await,
+            };
+            if let Some(allowed_hosts) = &settings.allowed_hosts {
+                // Checked in every parse mode, since with several `Host` fields the one that was allowed
+                // need not be the one the application reads.
+                let hosts = request.headers.get_all("Host").count();
+                if hosts != 1 || !host_allowed(allowed_hosts, request.host().as_ref()) {
+                    return Server::reject(&mut socket, addr, endpoint.middleware(), RequestError::Host).await;
+                }
+            }
+            request.remote_addr = Some(addr);
+            let version = request.version;
+            let keep_alive = Server::keep_alive(&request);
+            let is_head = request.method == Method::HEAD;
+
+            let mut response = Server::respond(&mut endpoint, request, settings.on_panic.as_ref()).await;
+            response.version(version);
+            if !keep_alive {
+                response.set_header("Connection", "close");
+            } else if version == Version::Http10 {
+                response.set_header("Connection", "keep-alive");
+            }
+            let built = if is_head {
+                response.build_head()
+            } else {
+                response.build()
+            };
+            socket.write_all(&built).await?;
+
+            if !keep_alive {
+                return Ok(());
+            }
+        }
+    }
 
-        router.lock().await.route(&mut ctx, &method, &path).await;
+    /// Answers `error` and closes the connection. The response skips the middleware chain, since there is no
+    /// request to run it on, but is passed to each middleware's [`Middleware::rejected`] so it can still be
+    /// logged or tagged. Whatever the client already sent is drained first, for a moment, so the close does
+    /// not reset the connection before the client has read the response.
+    async fn reject(
+        socket: &mut TcpStream,
+        addr: SocketAddr,
+        middleware: &[Arc<dyn Middleware>],
+        error: RequestError,
+    ) -> Result<()> {
+        let status = error.status();
+        let mut response = Response::new();
+        response.write_body(status.reason_phrase().as_bytes());
+        response.status_code(status);
+        response.set_header("Connection", "close");
 
+        let mut rejection = Rejection {
+            error,
+            remote_addr: addr,
+            response,
+            extensions: Extensions::new(),
+        };
+        for middleware in middleware {
+            middleware.rejected(&mut rejection);
+        }
+        socket.write_all(&rejection.response.build()).await?;
+        socket.shutdown().await?;
+
+        let mut discard = [0; 1024];
+        let drain = async { while socket.read(&mut discard).await.is_ok_and(|read| read > 0) {} };
+        let _ = tokio::time::timeout(LINGER, drain).await;
         Ok(())
     }
+
+    /// Runs the endpoint, answering 500 if it panics. Route panics are already answered inside the
+    /// middleware chain, so this only catches panics in the server middleware itself, or in a tower service.
+    /// The request was read in full beforehand, so the connection is left in a clean state and can be kept
+    /// alive.
+    async fn respond(endpoint: &mut Endpoint, request: Request, on_panic: Option<&PanicHook>) -> Response {
+        let method = request.method.clone();
+        let path = request.path.clone();
+
+        match CatchUnwind::new(Box::pin(endpoint.respond(request, on_panic))).await {
+            Ok(response) => response,
+            Err(caught) => {
+                let report = HandlerPanic {
+                    message: caught.message,
+                    location: caught.location,
+                    method,
+                    path: path.to_string(),
+                };
+                report_panic(on_panic, &report);
+                Server::internal_error()
+            }
+        }
+    }
+
+    fn internal_error() -> Response {
+        let mut response = Response::new();
+        response.status_code(StatusCode::InternalServerError);
+        response.write_body(b"Internal Server Error");
+        response
+    }
+
+    /// HTTP/1.1 connections persist unless the client sends `close`, HTTP/1.0 ones only with `keep-alive`.
+    fn keep_alive(request: &Request) -> bool {
+        let has_token = |option: &[u8]| {
+            request
+                .headers
+                .get_all("Connection")
+                .flat_map(|connection| connection.split(|b| *b == b','))
+                .any(|token| token.trim_ascii().eq_ignore_ascii_case(option))
+        };
+        match request.version {
+            Version::Http10 => has_token(b"keep-alive"),
+            Version::Http11 => !has_token(b"close"),
+        }
+    }
+
+    /// Reads from the socket until `bytes` starts with a complete request, and returns its length.
+    /// Returns `None` if the client closed the connection between requests.
+    async fn read_request(socket: &mut TcpStream, bytes: &mut BytesMut, settings: &Settings) -> Result<Option<usize>> {
+        loop {
+            if let Some(length) = request_length(bytes, &settings.limits, settings.max_body_size)? {
+                return Ok(Some(length));
+            }
+            if socket.read_buf(bytes).await? == 0 {
+                if bytes.is_empty() {
+                    return Ok(None);
+                }
+                bail!("connection closed before the request was complete");
+            }
+        }
+    }
+}
+
+fn report_panic(on_panic: Option<&PanicHook>, report: &HandlerPanic) {
+    match on_panic {
+        Some(hook) => hook(report),
+        None => eprintln!("{}", report),
+    }
+}
+
+/// Matches a normalized host against the allow-list. A pattern starting with `.` also matches subdomains.
+fn host_allowed(allowed_hosts: &[String], host: Option<&Host>) -> bool {
+    let Some(host) = host else {
+        return false;
+    };
+    allowed_hosts.iter().any(|pattern| match pattern.strip_prefix('.') {
+        Some(domain) => host.host == domain || host.host.ends_with(pattern.as_str()),
+        None => host.host == *pattern,
+    })
+}
+
+/// Length of the request at the start of `bytes`: the header section plus a `Content-Length` body.
+/// `None` if more bytes are needed. Fails as soon as the request line, header section or declared body
+/// is known to exceed its limit, without waiting for the rest.
+fn request_length(bytes: &[u8], limits: &Limits, max_body_size: usize) -> Result<Option<usize>, RequestError> {
+    let line_end = bytes.iter().position(|b| *b == b'\n').unwrap_or(bytes.len());
+    if line_end > limits.request_line + 1 {
+        return Err(RequestError::URITooLong);
+    }
+
+    let header_end = header_end(bytes);
+    let Some(header_end) = header_end.filter(|end| *end <= limits.header_bytes) else {
+        if header_end.is_some() || bytes.len() > limits.header_bytes {
+            return Err(RequestError::HeadersTooLarge);
+        }
+        return Ok(None);
+    };
+
+    let fields = bytes[..header_end].split(|b| *b == b'\n').skip(1).filter_map(|line| {
+        let colon = line.iter().position(|b| *b == b':')?;
+        Some((&line[..colon], &line[colon + 1..]))
+    });
+    let content_length = body_length(fields)?;
+    if content_length > max_body_size {
+        return Err(RequestError::BodyTooLarge);
+    }
+
+    let length = header_end + content_length;
+    Ok((bytes.len() >= length).then_some(length))
+}
+
+/// Offset just past the empty line that ends the header section. Lines end the way the parser reads them,
+/// at LF with an optional CR before it, so mixed line endings are framed the same as by the parser.
+fn header_end(bytes: &[u8]) -> Option<usize> {
+    let mut line_start = 0;
+    for (i, b) in bytes.iter().enumerate() {
+        if *b != b'\n' {
+            continue;
+        }
+        if matches!(&bytes[line_start..i], b"" | b"\r") {
+            return Some(i + 1);
+        }
+        line_start = i + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn frame(bytes: &[u8]) -> Result<Option<usize>, RequestError> {
+        request_length(bytes, &Limits::default(), 16)
+    }
+
+    #[test]
+    fn frame_requests() {
+        assert_eq!(frame(b"GET / HTTP/1.1\r\nHost: a\r\n").unwrap(), None);
+        assert_eq!(frame(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n").unwrap(), Some(27));
+        assert_eq!(frame(b"GET / HTTP/1.1\n\nGET /next").unwrap(), Some(16));
+        assert_eq!(frame(b"GET / HTTP/1.1\nHost: a\n\r\nGET /next").unwrap(), Some(25));
+        assert_eq!(frame(b"GET / HTTP/1.1\r\nHost: a\r\n\nGET /next").unwrap(), Some(26));
+        assert_eq!(frame(b"POST / HTTP/1.1\r\ncontent-length: 5\r\n\r\nhel").unwrap(), None);
+        assert_eq!(
+            frame(b"POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nhelloGET / HTTP/1.1").unwrap(),
+            Some(43)
+        );
+    }
+
+    #[rstest]
+    #[case("example.com", true)]
+    #[case("api.example.com", true)]
+    #[case("other.test", true)]
+    #[case("api.other.test", false)]
+    #[case("evilexample.com", false)]
+    fn allowed_hosts(#[case] host: &str, #[case] allowed: bool) {
+        let allowed_hosts = [".example.com".to_string(), "other.test".to_string()];
+        let host = Host {
+            host: host.to_string(),
+            port: Some(8080),
+        };
+        assert_eq!(host_allowed(&allowed_hosts, Some(&host)), allowed);
+        assert!(!host_allowed(&allowed_hosts, None));
+    }
+
+    #[test]
+    fn frame_limits() {
+        let long_uri = format!("GET /{} HTTP/1.1", "a".repeat(Limits::default().request_line));
+        assert!(matches!(frame(long_uri.as_bytes()), Err(RequestError::URITooLong)));
+
+        let long_header = format!(
+            "GET / HTTP/1.1\r\nX-Large: {}\r\n",
+            "a".repeat(Limits::default().header_bytes)
+        );
+        assert!(matches!(
+            frame(long_header.as_bytes()),
+            Err(RequestError::HeadersTooLarge)
+        ));
+
+        let large_body = b"POST / HTTP/1.1\r\nContent-Length: 17\r\n\r\n";
+        assert!(matches!(frame(large_body), Err(RequestError::BodyTooLarge)));
+    }
+
+    #[rstest]
+    #[case::transfer_encoding(
+        b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+        RequestError::TransferEncoding
+    )]
+    #[case::signed_content_length(
+        b"POST / HTTP/1.1\r\nContent-Length: +5\r\n\r\nhello",
+        RequestError::HeaderContentLength
+    )]
+    #[case::conflicting_content_length(
+        b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab",
+        RequestError::HeaderContentLength
+    )]
+    fn frame_rejects_ambiguous_bodies(#[case] bytes: &[u8], #[case] error: RequestError) {
+        assert_eq!(frame(bytes), Err(error));
+    }
+
+    /// Serves one connection with `server`, writes `raw` to it and returns everything sent back until the server
+    /// closes the connection.
+    async fn roundtrip(server: &Server, raw: &[u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let endpoint = server.endpoint();
+        let settings = Arc::new(server.settings.clone());
+        tokio::spawn(async move {
+            let (stream, addr) = listener.accept().await.unwrap();
+            Server::handle_connection(stream, addr, endpoint, settings).await
+        });
+
+        let mut client = TcpStream::connect(address).await.unwrap();
+        client.write_all(raw).await.unwrap();
+        let mut received = Vec::new();
+        client.read_to_end(&mut received).await.unwrap();
+        String::from_utf8(received).unwrap()
+    }
+
+    async fn boom(_ctx: &mut Context) {
+        panic!("handler failed");
+    }
+
+    async fn ok(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"ok");
+    }
+
+    #[tokio::test]
+    async fn mixed_line_endings_are_framed_like_the_parser() {
+        let mut router = Router::new();
+        router.get("/ok", ok);
+        let mut server = Server::new();
+        server.apply(router);
+
+        let received = roundtrip(
+            &server,
+            b"GET /ok HTTP/1.1\nHost: localhost\n\r\nGET /ok HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        assert_eq!(received.matches("HTTP/1.1 200 OK\r\n").count(), 2, "{}", received);
+    }
+
+    #[tokio::test]
+    async fn head_is_answered_without_a_body() {
+        let mut router = Router::new();
+        router.get("/ok", ok);
+        let mut server = Server::new();
+        server.apply(router);
+
+        let received = roundtrip(&server, b"HEAD /ok HTTP/1.1\r\nHost: localhost\r\n\r\nGET /ok HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await;
+
+        assert_eq!(
+            received,
+            "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nContent-Type: text/plain\r\n\r\n\
+             HTTP/1.1 200 OK\r\nContent-Length: 2\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\nok"
+        );
+    }
+
+    #[rstest]
+    #[case::allowed(
+        "GET /ok HTTP/1.1\r\nHost: example.com\r\nConnection: close\r\n\r\n",
+        "HTTP/1.1 200 OK\r\n"
+    )]
+    #[case::other_host("GET /ok HTTP/1.1\r\nHost: evil.test\r\n\r\n", "HTTP/1.1 400 Bad Request\r\n")]
+    #[case::duplicate_host(
+        "GET /ok HTTP/1.1\r\nHost: example.com\r\nHost: evil.test\r\n\r\n",
+        "HTTP/1.1 400 Bad Request\r\n"
+    )]
+    #[case::missing_host("GET /ok HTTP/1.0\r\n\r\n", "HTTP/1.1 400 Bad Request\r\n")]
+    #[tokio::test]
+    async fn allow_list_needs_one_host_in_lenient_mode(#[case] request: &str, #[case] status_line: &str) {
+        let mut router = Router::new();
+        router.get("/ok", ok);
+        let mut server = Server::new();
+        server.apply(router);
+        server.parse_mode(ParseMode::Lenient);
+        server.allowed_hosts(["example.com"]);
+
+        let received = roundtrip(&server, request.as_bytes()).await;
+
+        assert!(received.starts_with(status_line), "{}", received);
+    }
+
+    #[cfg(feature = "tower")]
+    #[tokio::test]
+    async fn tower_service_panics_reach_the_hook() {
+        async fn internal_error(ctx: &mut Context) {
+            ctx.response_mut().write_body(b"handled");
+        }
+
+        let mut router = Router::new();
+        router.get("/boom", boom);
+        router.error_handler(StatusCode::InternalServerError, internal_error);
+
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut server = Server::new();
+        server.serve(router);
+        let recorded = reports.clone();
+        server.on_panic(move |report| recorded.lock().unwrap().push(report.message.clone()));
+
+        let received = roundtrip(
+            &server,
+            b"GET /boom HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        )
+        .await;
+
+        assert!(received.ends_with("\r\n\r\nhandled"), "{}", received);
+        assert_eq!(*reports.lock().unwrap(), vec!["handler failed".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn panic_becomes_500_and_connection_stays_open() {
+        let mut router = Router::new();
+        router.get("/boom", boom);
+        router.get("/ok", ok);
+
+        let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut server = Server::new();
+        server.apply(router);
+        server.max_body_size(1024);
+        let recorded = reports.clone();
+        server.on_panic(move |report| recorded.lock().unwrap().push(report.clone()));
+        server.capture_panic_locations();
+
+        let received = roundtrip(&server, b"GET /boom HTTP/1.1\r\nHost: localhost\r\n\r\nGET /ok HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await;
+
+        assert!(
+            received.starts_with("HTTP/1.1 500 Internal Server Error\r\n"),
+            "{}",
+            received
+        );
+        assert!(
+            received.contains("\r\n\r\nInternal Server ErrorHTTP/1.1 200 OK\r\n"),
+            "{}",
+            received
+        );
+        assert!(received.contains("Connection: close\r\n"), "{}", received);
+        assert!(received.ends_with("\r\n\r\nok"), "{}", received);
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].message, "handler failed");
+        assert_eq!(reports[0].path, "/boom");
+        assert!(reports[0]
+            .location
+            .as_deref()
+            .is_some_and(|l| l.starts_with("src/server.rs:")));
+    }
+
+    #[rstest]
+    #[case::close_by_default("GET /ok HTTP/1.0\r\n\r\n", 1, "Connection: close\r\n")]
+    #[case::keep_alive(
+        "GET /ok HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\nGET /ok HTTP/1.0\r\n\r\n",
+        2,
+        "Connection: keep-alive\r\n"
+    )]
+    #[tokio::test]
+    async fn http_1_0_connections(#[case] requests: &str, #[case] responses: usize, #[case] connection: &str) {
+        let mut router = Router::new();
+        router.get("/ok", ok);
+        let mut server = Server::new();
+        server.apply(router);
+        server.max_body_size(1024);
+
+        let received = roundtrip(&server, requests.as_bytes()).await;
+
+        assert!(received.starts_with("HTTP/1.0 200 OK\r\n"), "{}", received);
+        assert!(received.contains(connection), "{}", received);
+        assert!(!received.contains("Transfer-Encoding"), "{}", received);
+        assert_eq!(received.matches("HTTP/1.0 200 OK").count(), responses, "{}", received);
+    }
+
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            std::io::Write::write(&mut *self.0.lock().unwrap(), buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn panics_and_rejections_reach_server_middleware() {
+        let mut router = Router::new();
+        router.get("/boom", boom);
+
+        let log = LogBuffer::default();
+        let mut server = Server::new();
+        server.apply(router);
+        server.on_panic(|_| {});
+        server.middleware(crate::SetRequestId::new().generator(|| "generated".to_string()));
+        server.middleware(crate::AccessLog::writer(crate::LogFormat::Common, log.clone()));
+
+        let received = roundtrip(
+            &server,
+            b"GET /boom HTTP/1.1\r\nHost: localhost\r\n\r\nBR@W / HTTP/1.1\r\n\r\n",
+        )
+        .await;
+
+        assert!(
+            received.starts_with("HTTP/1.1 500 Internal Server Error\r\n"),
+            "{}",
+            received
+        );
+        assert!(received.contains("HTTP/1.1 501 Not Implemented\r\n"), "{}", received);
+        assert_eq!(
+            received.matches("X-Request-Id: generated\r\n").count(),
+            2,
+            "{}",
+            received
+        );
+
+        // the access log is written on its own thread
+        let mut log_lines = String::new();
+        for _ in 0..100 {
+            log_lines = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+            if log_lines.lines().count() >= 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let log = log_lines;
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", log);
+        assert!(
+            lines[0].contains("\"GET /boom HTTP/1.1\" 500 21 \"generated\""),
+            "{}",
+            log
+        );
+        assert!(lines[1].contains("\"-\" 501 15 \"generated\""), "{}", log);
+    }
+
+    #[rstest]
+    #[case::idle("", "")]
+    #[case::partial_request("GET /ok HTTP/1.1\r\nHost: loc", "HTTP/1.1 408 Request Timeout\r\n")]
+    #[tokio::test]
+    async fn slow_clients_time_out(#[case] request: &str, #[case] expected: &str) {
+        let mut server = Server::new();
+        server.read_timeout(Duration::from_millis(50));
+
+        let received = roundtrip(&server, request.as_bytes()).await;
+
+        assert!(received.starts_with(expected), "{}", received);
+        assert_eq!(received.is_empty(), expected.is_empty(), "{}", received);
+    }
+
+    #[rstest]
+    #[case::bad_content_length(
+        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: five\r\n\r\n",
+        "HTTP/1.1 400 Bad Request\r\n"
+    )]
+    #[case::body_too_large(
+        "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2048\r\n\r\n",
+        "HTTP/1.1 413 Payload Too Large\r\n"
+    )]
+    #[case::chunked_body_is_not_a_second_request(
+        "POST /ok HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+         2e\r\nGET /ok HTTP/1.1\r\nHost: localhost\r\n\r\n\r\n0\r\n\r\n\
+         GET /ok HTTP/1.1\r\nHost: localhost\r\n\r\n",
+        "HTTP/1.1 501 Not Implemented\r\n"
+    )]
+    #[case::invalid_method("BR@W / HTTP/1.1\r\n\r\n", "HTTP/1.1 501 Not Implemented\r\n")]
+    #[case::unsupported_version("GET / HTTP/2.0\r\n\r\n", "HTTP/1.1 505 Http Version Not Supported\r\n")]
+    #[tokio::test]
+    async fn parse_errors_are_answered_and_close_the_connection(#[case] request: &str, #[case] status_line: &str) {
+        let mut router = Router::new();
+        router.get("/ok", ok);
+        let mut server = Server::new();
+        server.apply(router);
+        server.max_body_size(1024);
+
+        let received = roundtrip(&server, request.as_bytes()).await;
+
+        assert!(received.starts_with(status_line), "{}", received);
+        assert!(received.contains("Connection: close\r\n"), "{}", received);
+        assert_eq!(received.matches("HTTP/1.1 ").count(), 1, "{}", received);
+    }
 }
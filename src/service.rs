@@ -0,0 +1,103 @@
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+};
+
+use tower::{util::BoxCloneService, BoxError, Service, ServiceExt};
+
+use crate::{Context, HandlerPanic, Request, Response, Router};
+
+pub(crate) type BoxedService = BoxCloneService<Request, Response, BoxError>;
+
+impl Service<Request> for Router {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let router = self.clone();
+        Box::pin(async move {
+            let method = request.method.clone();
+            let path = request.path.clone();
+            let mut ctx = Context::new(request, Response::new());
+            router.route(&mut ctx, &method, &path).await;
+            if let Some(report) = ctx.remove::<HandlerPanic>() {
+                crate::server::report_service_panic(&report);
+            }
+            Ok(ctx.into_response())
+        })
+    }
+}
+
+pub(crate) fn boxed<S>(service: S) -> BoxedService
+where
+    S: Service<Request, Response = Response> + Clone + Send + 'static,
+    S::Error: Into<BoxError>,
+    S::Future: Send + 'static,
+{
+    BoxCloneService::new(service.map_err(Into::into))
+}
+
+/// Calls the service. The server answers a service error, a tower timeout for example, with 500.
+pub(crate) async fn respond(service: &mut BoxedService, request: Request) -> Result<Response, BoxError> {
+    service.ready().await?.call(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use httpstatus::StatusCode;
+    use std::time::Duration;
+    use tower::{timeout::TimeoutLayer, ServiceBuilder};
+
+    async fn hello(ctx: &mut Context) {
+        ctx.response_mut().write_body(b"hello");
+    }
+
+    async fn slow(ctx: &mut Context) {
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        ctx.response_mut().write_body(b"late");
+    }
+
+    fn request(path: &str) -> Request {
+        Request::new(Bytes::from(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path))).expect("parsing request")
+    }
+
+    #[tokio::test]
+    async fn router_is_a_service() {
+        let mut router = Router::new();
+        router.get("/", hello);
+
+        let response = router.clone().oneshot(request("/")).await.unwrap();
+        assert_eq!(response.body(), b"hello");
+
+        let response = router.oneshot(request("/missing")).await.unwrap();
+        assert_eq!(response.status(), &StatusCode::NotFound);
+    }
+
+    #[tokio::test]
+    async fn tower_layers_wrap_router() {
+        let mut router = Router::new();
+        router.get("/", hello);
+        router.get("/slow", slow);
+
+        let mut service = boxed(
+            ServiceBuilder::new()
+                .layer(TimeoutLayer::new(Duration::from_millis(10)))
+                .service(router),
+        );
+
+        let response = respond(&mut service, request("/")).await.unwrap();
+        assert_eq!(response.body(), b"hello");
+
+        let error = respond(&mut service, request("/slow")).await.unwrap_err();
+        assert!(error.is::<tower::timeout::error::Elapsed>());
+    }
+}
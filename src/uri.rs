@@ -0,0 +1,219 @@
+use core::fmt;
+
+use crate::{ByteStr, Method, RequestError};
+
+/// The components of a request target. Each one shares the buffer the request was parsed from.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Uri {
+    pub scheme: Option<ByteStr>,
+    pub authority: Option<ByteStr>,
+    pub path: ByteStr,
+    pub query: Option<ByteStr>,
+}
+
+/// Which of the RFC 9112 request-target forms was sent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TargetForm {
+    /// `/path?query`, sent to origin servers.
+    #[default]
+    Origin,
+    /// `http://host/path?query`, sent to proxies.
+    Absolute,
+    /// `host:port`, only with `CONNECT`.
+    Authority,
+    /// `*`, only with `OPTIONS`.
+    Asterisk,
+}
+
+/// The target of a request line, kept as sent and split into its [`Uri`] components.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestTarget {
+    pub form: TargetForm,
+    pub uri: Uri,
+    raw: ByteStr,
+}
+
+impl RequestTarget {
+    /// Parses `raw` in the form `method` calls for: authority-form for `CONNECT`, and origin-form,
+    /// absolute-form or `*` (for `OPTIONS` only) otherwise.
+    pub fn parse(raw: ByteStr, method: &Method) -> Result<Self, RequestError> {
+        let text = raw.as_str();
+        let (form, uri) = if *method == Method::CONNECT {
+            (TargetForm::Authority, RequestTarget::parse_authority_form(&raw)?)
+        } else if text == "*" && *method == Method::OPTIONS {
+            let uri = Uri {
+                path: raw.clone(),
+                ..Uri::default()
+            };
+            (TargetForm::Asterisk, uri)
+        } else if text.starts_with('/') {
+            (TargetForm::Origin, RequestTarget::split_path(&raw, text))
+        } else {
+            (TargetForm::Absolute, RequestTarget::parse_absolute_form(&raw)?)
+        };
+        Ok(RequestTarget { form, uri, raw })
+    }
+
+    /// The target as it appeared in the request line.
+    pub fn as_str(&self) -> &str {
+        self.raw.as_str()
+    }
+
+    /// The path to route on. Empty for authority-form targets.
+    pub fn path(&self) -> &ByteStr {
+        &self.uri.path
+    }
+
+    pub fn query(&self) -> Option<&str> {
+        self.uri.query.as_deref()
+    }
+
+    /// The `host[:port]` of absolute-form and authority-form targets.
+    pub fn authority(&self) -> Option<&str> {
+        self.uri.authority.as_deref()
+    }
+
+    fn parse_authority_form(raw: &ByteStr) -> Result<Uri, RequestError> {
+        let text = raw.as_str();
+        let (host, port) = text.rsplit_once(':').ok_or(RequestError::URI)?;
+        if !is_authority(text) || host.is_empty() || port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(RequestError::URI);
+        }
+        Ok(Uri {
+            authority: Some(raw.clone()),
+            ..Uri::default()
+        })
+    }
+
+    fn parse_absolute_form(raw: &ByteStr) -> Result<Uri, RequestError> {
+        let text = raw.as_str();
+        let (scheme, rest) = text.split_once("://").ok_or(RequestError::URI)?;
+        let valid_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'+' | b'-' | b'.'));
+        if !valid_scheme {
+            return Err(RequestError::URI);
+        }
+
+        let end = rest.find(['/', '?']).unwrap_or(rest.len());
+        let (authority, rest) = rest.split_at(end);
+        if authority.is_empty() || !is_authority(authority) {
+            return Err(RequestError::URI);
+        }
+
+        let mut uri = RequestTarget::split_path(raw, rest);
+        if uri.path.is_empty() {
+            uri.path = ByteStr::from_static("/");
+        }
+        uri.scheme = Some(raw.slice_ref(scheme));
+        uri.authority = Some(raw.slice_ref(authority));
+        Ok(uri)
+    }
+
+    /// Splits `text`, a slice of `raw` holding `path[?query]`.
+    fn split_path(raw: &ByteStr, text: &str) -> Uri {
+        let (path, query) = match text.split_once('?') {
+            Some((path, query)) => (path, Some(raw.slice_ref(query))),
+            None => (text, None),
+        };
+        Uri {
+            path: raw.slice_ref(path),
+            query,
+            ..Uri::default()
+        }
+    }
+}
+
+impl fmt::Display for RequestTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `host[:port]` characters. Paths, queries and fragments are not allowed, and neither is userinfo, which
+/// RFC 9112 section 3.2.2 forbids in request targets.
+fn is_authority(text: &str) -> bool {
+    !text.bytes().any(|b| matches!(b, b'/' | b'?' | b'#' | b'@'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn target(raw: &'static str, method: Method) -> Result<RequestTarget, RequestError> {
+        RequestTarget::parse(ByteStr::from_static(raw), &method)
+    }
+
+    #[rstest]
+    #[case("/users?id=7", TargetForm::Origin, None, None, "/users", Some("id=7"))]
+    #[case("/", TargetForm::Origin, None, None, "/", None)]
+    #[case(
+        "http://example.com/users?id=7",
+        TargetForm::Absolute,
+        Some("http"),
+        Some("example.com"),
+        "/users",
+        Some("id=7")
+    )]
+    #[case(
+        "https://example.com:8443",
+        TargetForm::Absolute,
+        Some("https"),
+        Some("example.com:8443"),
+        "/",
+        None
+    )]
+    #[case(
+        "http://example.com?q",
+        TargetForm::Absolute,
+        Some("http"),
+        Some("example.com"),
+        "/",
+        Some("q")
+    )]
+    fn parse_targets(
+        #[case] raw: &'static str,
+        #[case] form: TargetForm,
+        #[case] scheme: Option<&str>,
+        #[case] authority: Option<&str>,
+        #[case] path: &str,
+        #[case] query: Option<&str>,
+    ) {
+        let target = target(raw, Method::GET).unwrap();
+        assert_eq!(target.form, form);
+        assert_eq!(target.uri.scheme.as_deref(), scheme);
+        assert_eq!(target.authority(), authority);
+        assert_eq!(target.path(), path);
+        assert_eq!(target.query(), query);
+        assert_eq!(target.as_str(), raw);
+    }
+
+    #[test]
+    fn connect_and_options_targets() {
+        let connect = target("example.com:443", Method::CONNECT).unwrap();
+        assert_eq!(connect.form, TargetForm::Authority);
+        assert_eq!(connect.authority(), Some("example.com:443"));
+        assert_eq!(connect.path(), "");
+
+        let asterisk = target("*", Method::OPTIONS).unwrap();
+        assert_eq!(asterisk.form, TargetForm::Asterisk);
+        assert_eq!(asterisk.path(), "*");
+    }
+
+    #[rstest]
+    #[case("*", Method::GET)]
+    #[case("example.com", Method::GET)]
+    #[case("http:///path", Method::GET)]
+    #[case("1http://example.com/", Method::GET)]
+    #[case("http://user:pw@example.com/", Method::GET)]
+    #[case("http://user@example.com", Method::GET)]
+    #[case("/path", Method::CONNECT)]
+    #[case("example.com", Method::CONNECT)]
+    #[case("example.com:https", Method::CONNECT)]
+    #[case("user@example.com:443", Method::CONNECT)]
+    fn reject_targets(#[case] raw: &'static str, #[case] method: Method) {
+        assert_eq!(target(raw, method), Err(RequestError::URI));
+    }
+}